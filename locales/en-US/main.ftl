@@ -10,3 +10,18 @@ invalid-key = Invalid code
 key-expired = Key expired
 invalid-session = Invalid session
 session-expired = Session expired
+password-unchanged = Leave empty to keep the current password
+help-link = More info
+invalid-settings = Some settings are not valid, please correct them
+invalid-value = Invalid value
+value-required = This setting is required
+action-sent = Sent to the device
+list-add = Add
+list-remove = Remove
+list-move-up = Move up
+list-move-down = Move down
+map-key = Key
+map-value = Value
+setting-modified = Changed from default.
+reset-default = Reset
+reset-all = Reset to defaults
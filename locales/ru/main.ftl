@@ -10,3 +10,18 @@ invalid-key = Неправильный код
 key-expired = Ключ устарел
 invalid-session = Сессия не существует
 session-expired = Сессия устарела
+password-unchanged = Оставьте пустым, чтобы не менять пароль
+help-link = Подробнее
+invalid-settings = Некоторые настройки заданы неправильно, исправьте их
+invalid-value = Неправильное значение
+value-required = Обязательная настройка
+action-sent = Отправлено на устройство
+list-add = Добавить
+list-remove = Удалить
+list-move-up = Переместить вверх
+list-move-down = Переместить вниз
+map-key = Ключ
+map-value = Значение
+setting-modified = Отличается от значения по умолчанию.
+reset-default = Сбросить
+reset-all = Сбросить все
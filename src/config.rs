@@ -1,6 +1,14 @@
+use crate::format::StringFormat;
+use chrono::{NaiveDate, NaiveDateTime, NaiveTime};
+use chrono_tz::{Tz, TZ_VARIANTS};
 use core::convert::TryFrom;
+use fluent_templates::LanguageIdentifier;
+use regex::Regex;
 /// This module defines configuration items that we support
 use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::collections::{BTreeMap, HashSet};
+use url::Url;
 
 trait Validate {
     type Arg: ?Sized;
@@ -10,12 +18,112 @@ trait Validate {
     }
 }
 
-#[derive(Clone, Serialize, Deserialize)]
+/// Trait implementations of serde validate the default value
+#[derive(Clone, PartialEq, Serialize, Deserialize)]
+#[serde(remote = "Self")]
 pub struct ConfigItem {
     pub name: String,
-    pub title: String,
+    pub title: Title,
     #[serde(flatten)]
     pub value: ConfigValue,
+    /// Setting is shown only when the condition holds
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub visible_if: Option<Condition>,
+    /// Setting can be edited only when the condition holds
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub enabled_if: Option<Condition>,
+    /// Explanation shown under the input
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub description: Option<String>,
+    /// Hint shown in the empty input
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub placeholder: Option<String>,
+    /// Unit of the value, e.g. "ms" or "%"
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub unit: Option<String>,
+    /// Link to the documentation
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub help_url: Option<HelpUrl>,
+    /// Setting must not be left empty
+    #[serde(default, skip_serializing_if = "is_false")]
+    pub required: bool,
+    /// Value the setting can be reset to
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub default: Option<Value>,
+}
+
+impl Serialize for ConfigItem {
+    fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        ConfigItem::serialize(self, serializer)
+    }
+}
+
+impl<'de> Deserialize<'de> for ConfigItem {
+    fn deserialize<D: serde::Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        use serde::de::Error;
+        let mut item = ConfigItem::deserialize(deserializer)?;
+        if item.default.is_some() && !item.value.is_editable() {
+            return Err(D::Error::custom(
+                "default is not allowed for read-only items",
+            ));
+        }
+        if let Some(default) = &item.default {
+            // Stored in the same form as the value to be compared with it
+            let value = item
+                .value
+                .with_value(default)
+                .ok_or_else(|| D::Error::custom("default is not a valid value"))?;
+            item.default = value.to_json();
+        }
+        Ok(item)
+    }
+}
+
+fn is_false(b: &bool) -> bool {
+    !*b
+}
+
+/// Condition on the value of another setting
+#[derive(Clone, PartialEq, Serialize, Deserialize)]
+pub struct Condition {
+    /// Full name of the setting, e.g. `network.use_proxy`
+    pub name: String,
+    #[serde(flatten)]
+    pub test: Test,
+}
+
+#[derive(Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Test {
+    Equals(Value),
+    NotEquals(Value),
+    In(Vec<Value>),
+}
+
+impl Condition {
+    /// Evaluates condition against current values of settings
+    pub fn holds(&self, items: &[ConfigItem]) -> bool {
+        let value = match find_setting(items, &self.name).and_then(|i| i.value.to_json()) {
+            Some(v) => v,
+            None => return false,
+        };
+        match &self.test {
+            Test::Equals(v) => same_value(&value, v),
+            Test::NotEquals(v) => !same_value(&value, v),
+            Test::In(values) => values.iter().any(|v| same_value(&value, v)),
+        }
+    }
+}
+
+/// Compares values the same way as the web page does, e.g. `1` is the same as `1.0`
+fn same_value(a: &Value, b: &Value) -> bool {
+    match (a, b) {
+        (Value::Number(a), Value::Number(b)) => a.as_f64() == b.as_f64(),
+        (Value::Array(a), Value::Array(b)) => {
+            a.len() == b.len() && a.iter().zip(b).all(|(a, b)| same_value(a, b))
+        }
+        _ => a == b,
+    }
 }
 
 /// List of settings that we support
@@ -24,23 +132,114 @@ pub struct ConfigItem {
 #[serde(rename_all = "lowercase")]
 pub enum ConfigValue {
     String(ConfigString),
+    Text(ConfigText),
     Integer(ConfigInteger),
+    Float(ConfigFloat),
     Selection(ConfigSelection),
+    MultiSelection(ConfigMultiSelection),
     Bool(ConfigBool),
+    Password(ConfigPassword),
+    Group(ConfigGroup),
+    Info(ConfigInfo),
+    Action(ConfigAction),
+    List(ConfigList),
+    Map(ConfigMap),
+    Date(ConfigDate),
+    Time(ConfigTime),
+    DateTime(ConfigDateTime),
+    Timezone(ConfigTimezone),
+    Color(ConfigColor),
 }
 
 impl ConfigValue {
+    /// Current value in the same form as it is sent to the device
+    pub fn to_json(&self) -> Option<Value> {
+        serde_json::to_value(self)
+            .ok()
+            .and_then(|mut v| v.get_mut("value").map(Value::take))
+    }
+
+    /// Copy of the setting with another value, if the value is valid
+    pub fn with_value(&self, v: &Value) -> Option<ConfigValue> {
+        let mut json = serde_json::to_value(self).ok()?;
+        *json.get_mut("value")? = v.clone();
+        serde_json::from_value(json).ok()
+    }
+
+    /// Returns false for items which the user can not change
+    pub fn is_editable(&self) -> bool {
+        !matches!(
+            self,
+            ConfigValue::Group(_) | ConfigValue::Info(_) | ConfigValue::Action(_)
+        )
+    }
+
+    /// Returns true when nothing is entered by the user
+    pub fn is_empty(&self) -> bool {
+        match self {
+            ConfigValue::String(conf) => conf.value.is_empty(),
+            ConfigValue::Text(conf) => conf.value.is_empty(),
+            ConfigValue::Password(conf) => conf.value.is_empty(),
+            ConfigValue::MultiSelection(conf) => conf.value.is_empty(),
+            ConfigValue::List(conf) => conf.value.is_empty(),
+            ConfigValue::Map(conf) => conf.value.is_empty(),
+            ConfigValue::Date(conf) => conf.value.is_empty(),
+            ConfigValue::Time(conf) => conf.value.is_empty(),
+            ConfigValue::DateTime(conf) => conf.value.is_empty(),
+            _ => false,
+        }
+    }
+
+    /// Sets value from all form fields with the setting name
+    pub fn try_set_values(&mut self, values: &[String]) -> bool {
+        match self {
+            ConfigValue::MultiSelection(conf) => {
+                if conf.is_valid(values) {
+                    conf.0.value = values.to_vec();
+                    true
+                } else {
+                    false
+                }
+            }
+            ConfigValue::List(conf) => match conf.parse(values) {
+                Some(list) if conf.is_valid(&list) => {
+                    conf.0.value = list;
+                    true
+                }
+                _ => false,
+            },
+            ConfigValue::Map(conf) => match RawConfigMap::parse(values) {
+                Some(map) if conf.is_valid(&map) => {
+                    conf.0.value = map;
+                    true
+                }
+                _ => false,
+            },
+            _ => self.try_set_value(values.first().map_or("", String::as_str)),
+        }
+    }
+
     pub fn try_set_value(&mut self, s: &str) -> bool {
         match self {
             ConfigValue::String(conf) => {
                 if conf.is_valid(s) {
-                    conf.value = s.to_owned();
+                    conf.0.value = s.to_owned();
+                    true
+                } else {
+                    false
+                }
+            }
+            ConfigValue::Text(conf) => {
+                // Browsers submit CRLF line endings
+                let s = s.replace("\r\n", "\n").replace('\r', "\n");
+                if conf.is_valid(&s) {
+                    conf.0.value = s;
                     true
                 } else {
                     false
                 }
             }
-            ConfigValue::Integer(conf) => match s.parse::<u32>() {
+            ConfigValue::Integer(conf) => match s.parse::<i64>() {
                 Ok(i) => {
                     if conf.is_valid(&i) {
                         conf.0.value = i;
@@ -51,6 +250,17 @@ impl ConfigValue {
                 }
                 Err(_) => false,
             },
+            ConfigValue::Float(conf) => match s.parse::<f64>() {
+                Ok(f) => {
+                    if conf.is_valid(&f) {
+                        conf.0.value = f;
+                        true
+                    } else {
+                        false
+                    }
+                }
+                Err(_) => false,
+            },
             ConfigValue::Selection(conf) => {
                 if conf.is_valid(s) {
                     conf.0.value = s.to_owned();
@@ -59,6 +269,43 @@ impl ConfigValue {
                     false
                 }
             }
+            ConfigValue::Password(conf) => {
+                // Empty input means that password is unchanged
+                if !s.is_empty() {
+                    conf.value = s.to_owned();
+                }
+                true
+            }
+            ConfigValue::MultiSelection(_) | ConfigValue::List(_) | ConfigValue::Map(_) => {
+                if s.is_empty() {
+                    self.try_set_values(&[])
+                } else {
+                    self.try_set_values(&[s.to_owned()])
+                }
+            }
+            ConfigValue::Date(conf) => conf.try_set(s),
+            ConfigValue::Time(conf) => conf.try_set(s),
+            ConfigValue::DateTime(conf) => conf.try_set(s),
+            ConfigValue::Timezone(conf) => {
+                if conf.is_valid(s) {
+                    conf.0.value = s.to_owned();
+                    true
+                } else {
+                    false
+                }
+            }
+            ConfigValue::Color(conf) => {
+                if conf.is_valid(s) {
+                    conf.0.value = s.to_owned();
+                    true
+                } else {
+                    false
+                }
+            }
+            // Groups do not have own value
+            ConfigValue::Group(_) => false,
+            // Informational items and actions can not be changed by the user
+            ConfigValue::Info(_) | ConfigValue::Action(_) => true,
             ConfigValue::Bool(conf) => {
                 match s {
                     "on" => {
@@ -74,16 +321,152 @@ impl ConfigValue {
     }
 }
 
+impl ConfigItem {
+    pub fn new(name: String, title: Title, value: ConfigValue) -> Self {
+        Self {
+            name,
+            title,
+            value,
+            visible_if: None,
+            enabled_if: None,
+            description: None,
+            placeholder: None,
+            unit: None,
+            help_url: None,
+            required: false,
+            default: None,
+        }
+    }
+
+    /// Default value of the setting if it has one
+    pub fn default_value(&self) -> Option<ConfigValue> {
+        self.default.as_ref().and_then(|d| self.value.with_value(d))
+    }
+
+    /// Returns false when the setting is hidden or disabled by its conditions
+    fn is_active(&self, root: &[ConfigItem]) -> bool {
+        self.visible_if
+            .iter()
+            .chain(self.enabled_if.iter())
+            .all(|c| c.holds(root))
+    }
+
+    /// Replaces titles of the item with translations to the first of `langs` available
+    pub fn localize(&mut self, langs: &[LanguageIdentifier]) {
+        self.title = self.title.localize(langs).into();
+        match &mut self.value {
+            ConfigValue::Selection(conf) => {
+                conf.0.options.iter_mut().for_each(|c| c.localize(langs))
+            }
+            ConfigValue::MultiSelection(conf) => {
+                conf.0.options.iter_mut().for_each(|c| c.localize(langs))
+            }
+            ConfigValue::Group(group) => group.0.items.iter_mut().for_each(|i| i.localize(langs)),
+            _ => {}
+        }
+    }
+
+    /// Returns a copy of the item which is safe to show in the web interface
+    pub fn redacted(&self) -> Self {
+        let mut item = self.clone();
+        item.redact();
+        item
+    }
+
+    fn redact(&mut self) {
+        match &mut self.value {
+            ConfigValue::Password(conf) => conf.value.clear(),
+            ConfigValue::Group(group) => group.0.items.iter_mut().for_each(ConfigItem::redact),
+            _ => {}
+        }
+    }
+}
+
+/// Calls `f` for every setting with its full name,
+/// names of nested settings are joined with dots, e.g. `network.proxy.host`
+pub fn visit_settings_mut<F>(items: &mut [ConfigItem], f: &mut F)
+where
+    F: FnMut(&str, &mut ConfigItem),
+{
+    fn visit<F>(items: &mut [ConfigItem], prefix: &str, f: &mut F)
+    where
+        F: FnMut(&str, &mut ConfigItem),
+    {
+        for item in items {
+            let name = format!("{}{}", prefix, item.name);
+            match &mut item.value {
+                ConfigValue::Group(group) => visit(&mut group.0.items, &format!("{}.", name), f),
+                _ => f(&name, item),
+            }
+        }
+    }
+    visit(items, "", f)
+}
+
+/// Returns true when `f` holds for any setting, nested ones included
+pub fn any_setting<F>(items: &[ConfigItem], f: &F) -> bool
+where
+    F: Fn(&ConfigItem) -> bool,
+{
+    items.iter().any(|item| match &item.value {
+        ConfigValue::Group(group) => any_setting(&group.items, f),
+        _ => f(item),
+    })
+}
+
+/// Finds setting by its full name
+pub fn find_setting<'a>(items: &'a [ConfigItem], name: &str) -> Option<&'a ConfigItem> {
+    let mut parts = name.splitn(2, '.');
+    let head = parts.next()?;
+    let item = items.iter().find(|i| i.name == head)?;
+    match (parts.next(), &item.value) {
+        (None, _) => Some(item),
+        (Some(rest), ConfigValue::Group(group)) => find_setting(&group.items, rest),
+        _ => None,
+    }
+}
+
+/// Returns full names of settings which are hidden or disabled,
+/// either by their own conditions or by conditions of enclosing groups
+pub fn inactive_settings(items: &[ConfigItem]) -> HashSet<String> {
+    fn collect(
+        root: &[ConfigItem],
+        items: &[ConfigItem],
+        prefix: &str,
+        parent_active: bool,
+        out: &mut HashSet<String>,
+    ) {
+        for item in items {
+            let name = format!("{}{}", prefix, item.name);
+            let active = parent_active && item.is_active(root);
+            match &item.value {
+                ConfigValue::Group(group) => {
+                    collect(root, &group.items, &format!("{}.", name), active, out)
+                }
+                _ => {
+                    if !active {
+                        out.insert(name);
+                    }
+                }
+            }
+        }
+    }
+    let mut out = HashSet::new();
+    collect(items, items, "", true, &mut out);
+    out
+}
+
+/// Secret string that is never sent back to the web interface
 #[derive(Clone, PartialEq, Serialize, Deserialize)]
-pub struct ConfigString {
-    pub value: String,
+pub struct ConfigPassword {
+    value: String,
 }
 
-impl Validate for ConfigString {
+impl Validate for ConfigPassword {
     type Arg = str;
 }
 
-impl From<&str> for ConfigString {
+impl From<&str> for ConfigPassword {
     fn from(s: &str) -> Self {
         Self {
             value: s.to_owned(),
@@ -91,6 +474,43 @@ impl From<&str> for ConfigString {
     }
 }
 
+/// Read-only text provided by the device, e.g. firmware version or a warning
+#[derive(Clone, PartialEq, Serialize, Deserialize)]
+pub struct ConfigInfo {
+    value: String,
+    /// Text is shown as a banner of this style instead of a plain field
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    style: Option<InfoStyle>,
+}
+
+#[derive(Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum InfoStyle {
+    Info,
+    Warning,
+    Danger,
+}
+
+impl ConfigInfo {
+    pub fn new(value: String, style: Option<InfoStyle>) -> Self {
+        Self { value, style }
+    }
+}
+
+/// Button which sends a command to the device, e.g. reboot
+#[derive(Clone, PartialEq, Serialize, Deserialize)]
+pub struct ConfigAction {
+    /// Question the user must confirm before the command is sent
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    confirm: Option<String>,
+}
+
+impl ConfigAction {
+    pub fn new(confirm: Option<String>) -> Self {
+        Self { confirm }
+    }
+}
+
 #[derive(Clone, PartialEq, Serialize, Deserialize)]
 pub struct ConfigBool {
     value: bool,
@@ -112,11 +532,35 @@ impl From<bool> for ConfigBool {
     }
 }
 
+/// Control used to edit a number, the input field when not set
+#[derive(Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Widget {
+    Input,
+    Slider,
+}
+
 #[derive(Clone, PartialEq, Serialize, Deserialize)]
 pub struct RawConfigInteger {
-    min: u32,
-    max: u32,
-    value: u32,
+    min: i64,
+    max: i64,
+    /// Value must differ from `min` by a multiple of it, like in html5 forms
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    step: Option<i64>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    widget: Option<Widget>,
+    value: i64,
+}
+
+impl RawConfigInteger {
+    fn check(&self, v: i64) -> bool {
+        let aligned = match self.step {
+            // Difference does not fit into i64 for the widest ranges
+            Some(step) => (i128::from(v) - i128::from(self.min)) % i128::from(step) == 0,
+            None => true,
+        };
+        self.min <= v && v <= self.max && aligned
+    }
 }
 
 macro_rules! validated {
@@ -149,9 +593,9 @@ macro_rules! validated {
 }
 
 impl Validate for ConfigInteger {
-    type Arg = u32;
+    type Arg = i64;
     fn is_valid(&self, v: &Self::Arg) -> bool {
-        self.min <= *v && *v <= self.max
+        self.check(*v)
     }
 }
 
@@ -159,10 +603,14 @@ impl TryFrom<RawConfigInteger> for ConfigInteger {
     type Error = &'static str;
 
     fn try_from(raw: RawConfigInteger) -> Result<Self, Self::Error> {
-        if raw.min <= raw.value && raw.value <= raw.max {
-            Ok(Self(raw))
-        } else {
+        if raw.step.filter(|&step| step <= 0).is_some() {
+            Err("step is not positive")
+        } else if !(raw.min <= raw.value && raw.value <= raw.max) {
             Err("value is not in range")
+        } else if !raw.check(raw.value) {
+            Err("value is not a multiple of step")
+        } else {
+            Ok(Self(raw))
         }
     }
 }
@@ -170,8 +618,239 @@ impl TryFrom<RawConfigInteger> for ConfigInteger {
 validated! {#[derive(Clone, PartialEq)] pub ConfigInteger(RawConfigInteger)}
 
 impl ConfigInteger {
-    pub fn new(min: u32, max: u32, value: u32) -> Result<Self, &'static str> {
-        Self::try_from(RawConfigInteger { min, max, value })
+    pub fn new(min: i64, max: i64, step: Option<i64>, value: i64) -> Result<Self, &'static str> {
+        Self::try_from(RawConfigInteger {
+            min,
+            max,
+            step,
+            widget: None,
+            value,
+        })
+    }
+
+    pub fn with_widget(mut self, widget: Widget) -> Self {
+        self.0.widget = Some(widget);
+        self
+    }
+}
+
+#[derive(Clone, PartialEq, Serialize, Deserialize)]
+pub struct RawConfigString {
+    value: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    min_length: Option<usize>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    max_length: Option<usize>,
+    /// Regular expression that must match the whole value
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pattern: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    format: Option<StringFormat>,
+}
+
+/// Pattern is anchored the same way as html5 pattern attribute
+fn anchored_regex(pattern: &str) -> Result<Regex, regex::Error> {
+    Regex::new(&format!("^(?:{})$", pattern))
+}
+
+impl RawConfigString {
+    fn regex(&self) -> Option<Result<Regex, regex::Error>> {
+        self.pattern.as_deref().map(anchored_regex)
+    }
+
+    /// Constraints are not applied to an empty value, like in html5 forms
+    fn check(&self, v: &str) -> bool {
+        if v.is_empty() {
+            return true;
+        }
+        let len = v.chars().count();
+        if self.min_length.filter(|&min| len < min).is_some()
+            || self.max_length.filter(|&max| len > max).is_some()
+        {
+            return false;
+        }
+        if self.format.filter(|f| !f.is_valid(v)).is_some() {
+            return false;
+        }
+        match self.regex() {
+            Some(Ok(re)) => re.is_match(v),
+            Some(Err(_)) => false,
+            None => true,
+        }
+    }
+}
+
+validated! {#[derive(Clone, PartialEq)] pub ConfigString(RawConfigString)}
+
+impl Validate for ConfigString {
+    type Arg = str;
+    fn is_valid(&self, v: &Self::Arg) -> bool {
+        self.check(v)
+    }
+}
+
+impl TryFrom<RawConfigString> for ConfigString {
+    type Error = &'static str;
+
+    fn try_from(raw: RawConfigString) -> Result<Self, Self::Error> {
+        if let (Some(min), Some(max)) = (raw.min_length, raw.max_length) {
+            if min > max {
+                return Err("min_length is greater than max_length");
+            }
+        }
+        if let Some(Err(_)) = raw.regex() {
+            return Err("pattern is not a valid regular expression");
+        }
+        if raw.check(&raw.value) {
+            Ok(Self(raw))
+        } else {
+            Err("value does not satisfy constraints")
+        }
+    }
+}
+
+impl From<&str> for ConfigString {
+    fn from(s: &str) -> Self {
+        Self(RawConfigString {
+            value: s.to_owned(),
+            min_length: None,
+            max_length: None,
+            pattern: None,
+            format: None,
+        })
+    }
+}
+
+/// Multi-line string
+#[derive(Clone, PartialEq, Serialize, Deserialize)]
+pub struct RawConfigText {
+    value: String,
+    /// Height of the input box
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    rows: Option<u32>,
+    /// Maximum number of characters
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    max_length: Option<usize>,
+}
+
+validated! {#[derive(Clone, PartialEq)] pub ConfigText(RawConfigText)}
+
+impl ConfigText {
+    pub fn new(
+        value: String,
+        rows: Option<u32>,
+        max_length: Option<usize>,
+    ) -> Result<Self, &'static str> {
+        Self::try_from(RawConfigText {
+            value,
+            rows,
+            max_length,
+        })
+    }
+}
+
+impl Validate for ConfigText {
+    type Arg = str;
+    fn is_valid(&self, v: &Self::Arg) -> bool {
+        match self.max_length {
+            Some(max) => v.chars().count() <= max,
+            None => true,
+        }
+    }
+}
+
+impl TryFrom<RawConfigText> for ConfigText {
+    type Error = &'static str;
+
+    fn try_from(raw: RawConfigText) -> Result<Self, Self::Error> {
+        match raw.max_length {
+            Some(max) if raw.value.chars().count() > max => Err("value is too long"),
+            _ => Ok(Self(raw)),
+        }
+    }
+}
+
+#[derive(Clone, PartialEq, Serialize, Deserialize)]
+pub struct RawConfigFloat {
+    min: f64,
+    max: f64,
+    /// Granularity of the value, any value is allowed when not set
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    step: Option<f64>,
+    /// Number of digits after the decimal point displayed to the user
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    precision: Option<u8>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    widget: Option<Widget>,
+    value: f64,
+}
+
+impl RawConfigFloat {
+    fn check(&self, v: f64) -> bool {
+        let aligned = match self.step {
+            // Tolerate rounding errors of decimal steps, e.g. 0.1
+            Some(step) => {
+                let steps = (v - self.min) / step;
+                (steps - steps.round()).abs() <= 1e-9 * steps.abs().max(1.0)
+            }
+            None => true,
+        };
+        v.is_finite() && self.min <= v && v <= self.max && aligned
+    }
+}
+
+validated! {#[derive(Clone, PartialEq)] pub ConfigFloat(RawConfigFloat)}
+
+impl ConfigFloat {
+    pub fn new(
+        min: f64,
+        max: f64,
+        step: Option<f64>,
+        precision: Option<u8>,
+        value: f64,
+    ) -> Result<Self, &'static str> {
+        Self::try_from(RawConfigFloat {
+            min,
+            max,
+            step,
+            precision,
+            widget: None,
+            value,
+        })
+    }
+
+    pub fn with_widget(mut self, widget: Widget) -> Self {
+        self.0.widget = Some(widget);
+        self
+    }
+}
+
+impl Validate for ConfigFloat {
+    type Arg = f64;
+    fn is_valid(&self, v: &Self::Arg) -> bool {
+        self.check(*v)
+    }
+}
+
+impl TryFrom<RawConfigFloat> for ConfigFloat {
+    type Error = &'static str;
+
+    fn try_from(raw: RawConfigFloat) -> Result<Self, Self::Error> {
+        let step_ok = match raw.step {
+            Some(step) => step.is_finite() && step > 0.0,
+            None => true,
+        };
+        if !(raw.min.is_finite() && raw.max.is_finite()) {
+            Err("range is not finite")
+        } else if !step_ok {
+            Err("step is not positive")
+        } else if !(raw.min <= raw.value && raw.value <= raw.max) {
+            Err("value is not in range")
+        } else if !raw.check(raw.value) {
+            Err("value is not a multiple of step")
+        } else {
+            Ok(Self(raw))
+        }
     }
 }
 
@@ -214,13 +893,995 @@ impl TryFrom<RawConfigSelection> for ConfigSelection {
 }
 
 #[derive(Clone, PartialEq, Serialize, Deserialize)]
-pub struct Choice {
-    value: String,
-    title: String,
+pub struct RawConfigMultiSelection {
+    value: Vec<String>,
+    options: Vec<Choice>,
+    /// Minimal number of selected options
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    min: Option<usize>,
+    /// Maximal number of selected options
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    max: Option<usize>,
 }
 
-impl Choice {
-    pub fn new(value: String, title: String) -> Self {
-        Self { value, title }
+impl RawConfigMultiSelection {
+    fn check(&self, values: &[String]) -> bool {
+        let n = values.len();
+        self.min.filter(|&min| n < min).is_none()
+            && self.max.filter(|&max| n > max).is_none()
+            && values.iter().enumerate().all(|(i, v)| {
+                self.options.iter().any(|elem| elem.value == *v) && !values[..i].contains(v)
+            })
+    }
+}
+
+validated! {#[derive(Clone, PartialEq)] pub ConfigMultiSelection(RawConfigMultiSelection)}
+
+impl ConfigMultiSelection {
+    pub fn new(
+        value: Vec<String>,
+        options: Vec<Choice>,
+        min: Option<usize>,
+        max: Option<usize>,
+    ) -> Result<Self, &'static str> {
+        Self::try_from(RawConfigMultiSelection {
+            value,
+            options,
+            min,
+            max,
+        })
+    }
+}
+
+impl Validate for ConfigMultiSelection {
+    type Arg = [String];
+    fn is_valid(&self, v: &Self::Arg) -> bool {
+        self.check(v)
+    }
+}
+
+impl TryFrom<RawConfigMultiSelection> for ConfigMultiSelection {
+    type Error = &'static str;
+
+    fn try_from(raw: RawConfigMultiSelection) -> Result<Self, Self::Error> {
+        if let (Some(min), Some(max)) = (raw.min, raw.max) {
+            if min > max {
+                return Err("min is greater than max");
+            }
+        }
+        if raw.check(&raw.value) {
+            Ok(Self(raw))
+        } else {
+            Err("value does not match choices")
+        }
+    }
+}
+
+/// Type of the list entries
+#[derive(Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ListItemType {
+    String,
+    Integer,
+}
+
+impl ListItemType {
+    /// Converts form input into the list entry
+    fn parse(self, s: &str) -> Option<Value> {
+        match self {
+            ListItemType::String => Some(Value::from(s)),
+            ListItemType::Integer => s.trim().parse::<i64>().ok().map(Value::from),
+        }
+    }
+
+    fn matches(self, v: &Value) -> bool {
+        match self {
+            ListItemType::String => v.is_string(),
+            ListItemType::Integer => v.is_i64(),
+        }
+    }
+}
+
+/// Ordered list of values entered by the user
+#[derive(Clone, PartialEq, Serialize, Deserialize)]
+pub struct RawConfigList {
+    value: Vec<Value>,
+    item_type: ListItemType,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    min_length: Option<usize>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    max_length: Option<usize>,
+    /// User can change the order of the entries
+    #[serde(default, skip_serializing_if = "is_false")]
+    reorderable: bool,
+}
+
+impl RawConfigList {
+    fn check(&self, values: &[Value]) -> bool {
+        let n = values.len();
+        self.min_length.filter(|&min| n < min).is_none()
+            && self.max_length.filter(|&max| n > max).is_none()
+            && values.iter().all(|v| self.item_type.matches(v))
+    }
+
+    /// Parses form inputs keeping their order, blank inputs are skipped
+    fn parse(&self, values: &[String]) -> Option<Vec<Value>> {
+        values
+            .iter()
+            .filter(|s| !s.trim().is_empty())
+            .map(|s| self.item_type.parse(s))
+            .collect()
+    }
+}
+
+validated! {#[derive(Clone, PartialEq)] pub ConfigList(RawConfigList)}
+
+impl ConfigList {
+    pub fn new(
+        value: Vec<Value>,
+        item_type: ListItemType,
+        reorderable: bool,
+    ) -> Result<Self, &'static str> {
+        Self::try_from(RawConfigList {
+            value,
+            item_type,
+            min_length: None,
+            max_length: None,
+            reorderable,
+        })
+    }
+}
+
+impl Validate for ConfigList {
+    type Arg = [Value];
+    fn is_valid(&self, v: &Self::Arg) -> bool {
+        self.check(v)
+    }
+}
+
+impl TryFrom<RawConfigList> for ConfigList {
+    type Error = &'static str;
+
+    fn try_from(raw: RawConfigList) -> Result<Self, Self::Error> {
+        if let (Some(min), Some(max)) = (raw.min_length, raw.max_length) {
+            if min > max {
+                return Err("min_length is greater than max_length");
+            }
+        }
+        if raw.check(&raw.value) {
+            Ok(Self(raw))
+        } else {
+            Err("value does not match item_type or length")
+        }
+    }
+}
+
+/// String values by string keys, e.g. custom http headers
+#[derive(Clone, PartialEq, Serialize, Deserialize)]
+pub struct RawConfigMap {
+    value: BTreeMap<String, String>,
+    /// Regular expression that must match the whole key
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    key_pattern: Option<String>,
+}
+
+impl RawConfigMap {
+    fn regex(&self) -> Option<Result<Regex, regex::Error>> {
+        self.key_pattern.as_deref().map(anchored_regex)
+    }
+
+    fn check(&self, map: &BTreeMap<String, String>) -> bool {
+        let re = match self.regex() {
+            Some(Ok(re)) => Some(re),
+            Some(Err(_)) => return false,
+            None => None,
+        };
+        map.keys()
+            .all(|k| !k.is_empty() && re.iter().all(|re| re.is_match(k)))
+    }
+
+    /// Form inputs go in pairs of key and value, rows with both of them blank are skipped.
+    /// Returns None for a value without key and for repeated keys.
+    fn parse(values: &[String]) -> Option<BTreeMap<String, String>> {
+        let mut map = BTreeMap::new();
+        for pair in values.chunks(2) {
+            let (key, value) = match pair {
+                [key, value] => (key.trim(), value),
+                _ => return None,
+            };
+            if key.is_empty() && value.is_empty() {
+                continue;
+            }
+            if key.is_empty() || map.insert(key.to_owned(), value.to_owned()).is_some() {
+                return None;
+            }
+        }
+        Some(map)
+    }
+}
+
+validated! {#[derive(Clone, PartialEq)] pub ConfigMap(RawConfigMap)}
+
+impl ConfigMap {
+    pub fn new(
+        value: BTreeMap<String, String>,
+        key_pattern: Option<String>,
+    ) -> Result<Self, &'static str> {
+        Self::try_from(RawConfigMap { value, key_pattern })
+    }
+}
+
+impl Validate for ConfigMap {
+    type Arg = BTreeMap<String, String>;
+    fn is_valid(&self, v: &Self::Arg) -> bool {
+        self.check(v)
+    }
+}
+
+impl TryFrom<RawConfigMap> for ConfigMap {
+    type Error = &'static str;
+
+    fn try_from(raw: RawConfigMap) -> Result<Self, Self::Error> {
+        if let Some(Err(_)) = raw.regex() {
+            return Err("key_pattern is not a valid regular expression");
+        }
+        if raw.check(&raw.value) {
+            Ok(Self(raw))
+        } else {
+            Err("keys do not match key_pattern")
+        }
+    }
+}
+
+/// Date and time values in ISO-8601 format used by html inputs,
+/// an empty value means that it is not set
+#[derive(Clone, PartialEq, Serialize, Deserialize)]
+pub struct RawConfigTemporal {
+    value: String,
+}
+
+/// Defines setting for one kind of date and time values
+macro_rules! temporal {
+    ($type:ident, $formats:expr, $parse:path) => {
+        validated! {#[derive(Clone, PartialEq)] pub $type(RawConfigTemporal)}
+
+        impl $type {
+            pub fn new(value: String) -> Result<Self, &'static str> {
+                Self::try_from(RawConfigTemporal { value })
+            }
+
+            fn check(v: &str) -> bool {
+                v.is_empty() || $formats.iter().any(|f| $parse(v, f).is_ok())
+            }
+
+            fn try_set(&mut self, s: &str) -> bool {
+                if self.is_valid(s) {
+                    self.0.value = s.to_owned();
+                    true
+                } else {
+                    false
+                }
+            }
+        }
+
+        impl Validate for $type {
+            type Arg = str;
+            fn is_valid(&self, v: &Self::Arg) -> bool {
+                Self::check(v)
+            }
+        }
+
+        impl TryFrom<RawConfigTemporal> for $type {
+            type Error = &'static str;
+
+            fn try_from(raw: RawConfigTemporal) -> Result<Self, Self::Error> {
+                if Self::check(&raw.value) {
+                    Ok(Self(raw))
+                } else {
+                    Err("value is not in ISO-8601 format")
+                }
+            }
+        }
+    };
+}
+
+temporal!(ConfigDate, ["%Y-%m-%d"], NaiveDate::parse_from_str);
+temporal!(ConfigTime, ["%H:%M", "%H:%M:%S"], NaiveTime::parse_from_str);
+temporal!(
+    ConfigDateTime,
+    ["%Y-%m-%dT%H:%M", "%Y-%m-%dT%H:%M:%S"],
+    NaiveDateTime::parse_from_str
+);
+
+/// IANA time zone name, e.g. `Europe/Moscow`
+#[derive(Clone, PartialEq, Serialize, Deserialize)]
+pub struct RawConfigTimezone {
+    value: String,
+}
+
+validated! {#[derive(Clone, PartialEq)] pub ConfigTimezone(RawConfigTimezone)}
+
+impl ConfigTimezone {
+    pub fn new(value: String) -> Result<Self, &'static str> {
+        Self::try_from(RawConfigTimezone { value })
+    }
+}
+
+impl Validate for ConfigTimezone {
+    type Arg = str;
+    fn is_valid(&self, v: &Self::Arg) -> bool {
+        v.parse::<Tz>().is_ok()
+    }
+}
+
+impl TryFrom<RawConfigTimezone> for ConfigTimezone {
+    type Error = &'static str;
+
+    fn try_from(raw: RawConfigTimezone) -> Result<Self, Self::Error> {
+        if raw.value.parse::<Tz>().is_ok() {
+            Ok(Self(raw))
+        } else {
+            Err("value is not a known time zone")
+        }
+    }
+}
+
+/// Names of all known time zones offered to the user,
+/// so that devices do not have to send them
+pub fn timezone_names() -> Vec<&'static str> {
+    let mut names: Vec<_> = TZ_VARIANTS.iter().map(|tz| tz.name()).collect();
+    names.sort_unstable();
+    names
+}
+
+/// Color in `#RRGGBB` form, or `#RRGGBBAA` when `alpha` is allowed
+#[derive(Clone, PartialEq, Serialize, Deserialize)]
+pub struct RawConfigColor {
+    value: String,
+    #[serde(default, skip_serializing_if = "is_false")]
+    alpha: bool,
+}
+
+validated! {#[derive(Clone, PartialEq)] pub ConfigColor(RawConfigColor)}
+
+impl ConfigColor {
+    pub fn new(value: String, alpha: bool) -> Result<Self, &'static str> {
+        Self::try_from(RawConfigColor { value, alpha })
+    }
+
+    fn check(v: &str, alpha: bool) -> bool {
+        if !v.starts_with('#') {
+            return false;
+        }
+        let digits = &v[1..];
+        (digits.len() == 6 || alpha && digits.len() == 8)
+            && digits.chars().all(|c| c.is_ascii_hexdigit())
+    }
+}
+
+impl Validate for ConfigColor {
+    type Arg = str;
+    fn is_valid(&self, v: &Self::Arg) -> bool {
+        Self::check(v, self.alpha)
+    }
+}
+
+impl TryFrom<RawConfigColor> for ConfigColor {
+    type Error = &'static str;
+
+    fn try_from(raw: RawConfigColor) -> Result<Self, Self::Error> {
+        if Self::check(&raw.value, raw.alpha) {
+            Ok(Self(raw))
+        } else {
+            Err("color must be in #RRGGBB form, or #RRGGBBAA if alpha is allowed")
+        }
+    }
+}
+
+validated! {#[derive(Clone, PartialEq)] pub HelpUrl(String)}
+
+impl TryFrom<String> for HelpUrl {
+    type Error = &'static str;
+
+    fn try_from(raw: String) -> Result<Self, Self::Error> {
+        match Url::parse(&raw) {
+            Ok(url) if url.scheme() == "http" || url.scheme() == "https" => Ok(Self(raw)),
+            _ => Err("help_url must be http(s) url"),
+        }
+    }
+}
+
+/// Settings that are shown together
+#[derive(Clone, PartialEq, Serialize, Deserialize)]
+pub struct RawConfigGroup {
+    items: Vec<ConfigItem>,
+}
+
+validated! {#[derive(Clone, PartialEq)] pub ConfigGroup(RawConfigGroup)}
+
+impl ConfigGroup {
+    pub fn new(items: Vec<ConfigItem>) -> Result<Self, &'static str> {
+        Self::try_from(RawConfigGroup { items })
+    }
+}
+
+impl TryFrom<RawConfigGroup> for ConfigGroup {
+    type Error = &'static str;
+
+    fn try_from(raw: RawConfigGroup) -> Result<Self, Self::Error> {
+        check_names(&raw.items)?;
+        Ok(Self(raw))
+    }
+}
+
+/// Checks names of settings on the same level, they are joined with dots into full names.
+/// Names starting with underscore are reserved for control fields of the web form.
+fn check_names(items: &[ConfigItem]) -> Result<(), &'static str> {
+    for (i, item) in items.iter().enumerate() {
+        if item.name.is_empty() || item.name.contains('.') {
+            return Err("name of setting must be non empty and must not contain dots");
+        }
+        if item.name.starts_with('_') {
+            return Err("names of settings starting with underscore are reserved");
+        }
+        if items[..i].iter().any(|other| other.name == item.name) {
+            return Err("names of settings must be unique");
+        }
+    }
+    Ok(())
+}
+
+/// Checks the whole list of settings sent by the device,
+/// settings nested in groups are checked when the groups are parsed
+pub fn validate_settings(items: &[ConfigItem]) -> Result<(), &'static str> {
+    check_names(items)?;
+    check_conditions(items, items)
+}
+
+/// Conditions must refer to settings with a single input the page can read,
+/// otherwise the page and the server disagree whether they hold.
+/// Password is never sent to the page, and must not be revealed by a condition.
+fn check_conditions(root: &[ConfigItem], items: &[ConfigItem]) -> Result<(), &'static str> {
+    let observable = |name: &str| match find_setting(root, name).map(|i| &i.value) {
+        Some(ConfigValue::Password(_)) | Some(ConfigValue::List(_)) | Some(ConfigValue::Map(_)) => {
+            false
+        }
+        Some(value) => value.is_editable(),
+        None => false,
+    };
+    for item in items {
+        let known = item
+            .visible_if
+            .iter()
+            .chain(item.enabled_if.iter())
+            .all(|c| observable(&c.name));
+        if !known {
+            return Err(
+                "condition must refer to an editable setting other than password, list or map",
+            );
+        }
+        if let ConfigValue::Group(group) = &item.value {
+            check_conditions(root, &group.items)?;
+        }
+    }
+    Ok(())
+}
+
+#[derive(Clone, PartialEq, Serialize, Deserialize)]
+pub struct Choice {
+    value: String,
+    title: Title,
+}
+
+impl Choice {
+    pub fn new(value: String, title: Title) -> Self {
+        Self { value, title }
+    }
+
+    fn localize(&mut self, langs: &[LanguageIdentifier]) {
+        self.title = self.title.localize(langs).into();
+    }
+}
+
+/// Title shown to the user, either the same for all languages
+/// or translations by language tags, e.g. `{"en-US": "Volume", "ru": "Громкость"}`
+#[derive(Clone, PartialEq, Serialize, Deserialize)]
+#[serde(untagged)]
+pub enum Title {
+    Plain(String),
+    Localized(Translations),
+}
+
+impl Title {
+    /// Picks translation for the first of `langs` that has one, matching either
+    /// the whole language tag or only the language, e.g. `ru` for `ru-RU`.
+    /// Falls back to any translation when none of them matches.
+    pub fn localize(&self, langs: &[LanguageIdentifier]) -> &str {
+        let translations = match self {
+            Title::Plain(s) => return s,
+            Title::Localized(translations) => translations,
+        };
+        let tags: Vec<(LanguageIdentifier, &String)> = translations
+            .iter()
+            .filter_map(|(tag, s)| Some((tag.parse().ok()?, s)))
+            .collect();
+        langs
+            .iter()
+            .find_map(|lang| {
+                let same_language =
+                    |(tag, _): &&(LanguageIdentifier, &String)| tag.language == lang.language;
+                tags.iter()
+                    .find(|(tag, _)| tag == lang)
+                    .or_else(|| tags.iter().find(same_language))
+            })
+            .map(|(_, s)| s.as_str())
+            .or_else(|| translations.values().next().map(String::as_str))
+            .unwrap_or_default()
+    }
+}
+
+impl From<&str> for Title {
+    fn from(s: &str) -> Self {
+        Title::Plain(s.to_owned())
+    }
+}
+
+validated! {#[derive(Clone, PartialEq)] pub Translations(BTreeMap<String, String>)}
+
+impl TryFrom<BTreeMap<String, String>> for Translations {
+    type Error = &'static str;
+
+    fn try_from(raw: BTreeMap<String, String>) -> Result<Self, Self::Error> {
+        if raw.is_empty() {
+            return Err("translations must not be empty");
+        }
+        if raw
+            .keys()
+            .any(|tag| tag.parse::<LanguageIdentifier>().is_err())
+        {
+            return Err("translations must have valid language tags");
+        }
+        Ok(Self(raw))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn parse(v: serde_json::Value) -> Result<ConfigValue, serde_json::Error> {
+        serde_json::from_value(v)
+    }
+
+    #[test]
+    fn integer_range() {
+        let old = parse(json!({"type": "integer", "min": 0, "max": 100, "value": 33}));
+        assert!(old.is_ok());
+
+        let mut signed =
+            parse(json!({"type": "integer", "min": -12, "max": 12, "value": -3})).unwrap();
+        assert!(signed.try_set_value("-12"));
+        assert!(!signed.try_set_value("-13"));
+        assert!(!signed.try_set_value("1.5"));
+
+        let bad = parse(json!({"type": "integer", "min": -12, "max": 12, "value": 13}));
+        assert!(bad.is_err());
+    }
+
+    #[test]
+    fn integer_step() {
+        let mut stepped = parse(json!({
+            "type": "integer", "min": -10, "max": 100, "step": 5, "value": 0, "widget": "slider",
+        }))
+        .unwrap();
+        assert!(stepped.try_set_value("-5"));
+        assert!(stepped.try_set_value("100"));
+        assert!(!stepped.try_set_value("3"));
+        assert!(!stepped.try_set_value("105"));
+        let widest = json!({
+            "type": "integer", "min": i64::MIN, "max": i64::MAX, "step": 2, "value": 0,
+        });
+        let mut widest = parse(widest).unwrap();
+        assert!(widest.try_set_value(&(i64::MAX - 1).to_string()));
+        assert!(!widest.try_set_value(&i64::MAX.to_string()));
+
+        let stepped = |step, value| {
+            parse(json!({"type": "integer", "min": 0, "max": 10, "step": step, "value": value}))
+        };
+        assert!(stepped(2, 3).is_err());
+        assert!(stepped(0, 0).is_err());
+        assert!(stepped(-2, 0).is_err());
+        assert!(
+            parse(json!({"type": "float", "min": 0, "max": 1, "value": 0, "widget": "knob"}))
+                .is_err()
+        );
+    }
+
+    #[test]
+    fn float_range_and_step() {
+        let mut f = parse(json!({"type": "float", "min": -1, "max": 1, "value": 0.25})).unwrap();
+        assert!(f.try_set_value("-0.37"));
+        assert!(f.try_set_value("1"));
+        assert!(!f.try_set_value("1.01"));
+        assert!(!f.try_set_value("NaN"));
+        assert!(!f.try_set_value("inf"));
+        assert!(!f.try_set_value("abc"));
+
+        let mut stepped = parse(json!({
+            "type": "float", "min": 0.1, "max": 1, "step": 0.1, "precision": 1, "value": 0.3,
+        }))
+        .unwrap();
+        assert!(stepped.try_set_value("0.7"));
+        assert!(stepped.try_set_value("1"));
+        assert!(!stepped.try_set_value("0.37"));
+        assert!(!stepped.try_set_value("0"));
+
+        let float =
+            |v| parse(json!({"type": "float", "min": 0, "max": 1, "step": 0.5, "value": v}));
+        assert!(float(json!(0.5)).is_ok());
+        assert!(float(json!(0.37)).is_err());
+        assert!(float(json!(2)).is_err());
+        assert!(
+            parse(json!({"type": "float", "min": 0, "max": 1, "step": 0, "value": 0})).is_err()
+        );
+    }
+
+    #[test]
+    fn string_constraints() {
+        let mut s = parse(json!({
+            "type": "string",
+            "value": "",
+            "min_length": 2,
+            "max_length": 4,
+            "pattern": "[a-z]+",
+        }))
+        .unwrap();
+        assert!(s.try_set_value("abc"));
+        assert!(s.try_set_value(""));
+        assert!(!s.try_set_value("a"));
+        assert!(!s.try_set_value("abcde"));
+        assert!(!s.try_set_value("ab1"));
+
+        let bad = parse(json!({"type": "string", "value": "", "pattern": "[a-z"}));
+        assert!(bad.is_err());
+        let bad = parse(json!({"type": "string", "value": "", "min_length": 3, "max_length": 2}));
+        assert!(bad.is_err());
+        let bad = parse(json!({"type": "string", "value": "A", "pattern": "[a-z]+"}));
+        assert!(bad.is_err());
+    }
+
+    #[test]
+    fn string_format() {
+        let mut s = parse(json!({"type": "string", "value": "", "format": "host:port"})).unwrap();
+        assert!(s.try_set_value("example.com:8080"));
+        assert!(!s.try_set_value("example.com"));
+
+        let bad = parse(json!({"type": "string", "value": "x", "format": "ipv4"}));
+        assert!(bad.is_err());
+        let bad = parse(json!({"type": "string", "value": "", "format": "phone"}));
+        assert!(bad.is_err());
+    }
+
+    #[test]
+    fn multiselection() {
+        let mut m = parse(json!({
+            "type": "multiselection",
+            "value": ["a"],
+            "options": [
+                {"value": "a", "title": "A"},
+                {"value": "b", "title": "B"},
+                {"value": "c", "title": "C"},
+            ],
+            "max": 2,
+        }))
+        .unwrap();
+        assert!(m.try_set_values(&["b".into(), "a".into()]));
+        assert!(m.try_set_values(&[]));
+        assert!(!m.try_set_values(&["a".into(), "b".into(), "c".into()]));
+        assert!(!m.try_set_values(&["a".into(), "a".into()]));
+        assert!(!m.try_set_values(&["d".into()]));
+    }
+
+    #[test]
+    fn list() {
+        let mut l = parse(json!({
+            "type": "list",
+            "item_type": "integer",
+            "value": [3, 1, 2],
+            "max_length": 3,
+        }))
+        .unwrap();
+        assert!(l.try_set_values(&["2".into(), "".into(), "1".into(), " 3 ".into()]));
+        assert!(l.to_json() == Some(json!([2, 1, 3])));
+        assert!(!l.try_set_values(&["1".into(), "x".into()]));
+        assert!(!l.try_set_values(&["1".into(), "2".into(), "3".into(), "4".into()]));
+        assert!(l.try_set_values(&[]));
+        assert!(l.is_empty());
+
+        assert!(parse(json!({"type": "list", "item_type": "integer", "value": ["a"]})).is_err());
+        assert!(parse(json!({"type": "list", "item_type": "string", "value": ["a"]})).is_ok());
+    }
+
+    #[test]
+    fn map() {
+        let mut m = parse(json!({
+            "type": "map",
+            "value": {"X-Token": "abc"},
+            "key_pattern": "[A-Za-z-]+",
+        }))
+        .unwrap();
+        let form = |v: &[&str]| v.iter().map(|s| s.to_string()).collect::<Vec<_>>();
+        assert!(m.try_set_values(&form(&["Accept", "*/*", "", "", "X-Id", "1"])));
+        assert!(m.to_json() == Some(json!({"Accept": "*/*", "X-Id": "1"})));
+        assert!(!m.try_set_values(&form(&["Accept", "a", "Accept", "b"])));
+        assert!(!m.try_set_values(&form(&["", "value"])));
+        assert!(!m.try_set_values(&form(&["X_Id", "1"])));
+        assert!(!m.try_set_values(&form(&["Accept"])));
+
+        assert!(
+            parse(json!({"type": "map", "value": {"1": "a"}, "key_pattern": "[a-z]+"})).is_err()
+        );
+        assert!(parse(json!({"type": "map", "value": {}, "key_pattern": "("})).is_err());
+    }
+
+    #[test]
+    fn date_and_time() {
+        let mut d = parse(json!({"type": "date", "value": "2020-02-29"})).unwrap();
+        assert!(d.try_set_value("2021-12-31"));
+        assert!(d.try_set_value(""));
+        assert!(!d.try_set_value("2021-02-29"));
+        assert!(!d.try_set_value("31.12.2021"));
+
+        let mut t = parse(json!({"type": "time", "value": "23:00"})).unwrap();
+        assert!(t.try_set_value("07:30:15"));
+        assert!(!t.try_set_value("24:00"));
+        assert!(!t.try_set_value("7 am"));
+
+        let mut dt = parse(json!({"type": "datetime", "value": ""})).unwrap();
+        assert!(dt.try_set_value("2021-12-31T23:59"));
+        assert!(!dt.try_set_value("2021-12-31 23:59"));
+        assert!(!dt.try_set_value("2021-12-31"));
+
+        assert!(parse(json!({"type": "time", "value": "noon"})).is_err());
+    }
+
+    #[test]
+    fn timezone() {
+        let mut tz = parse(json!({"type": "timezone", "value": "Europe/Moscow"})).unwrap();
+        assert!(tz.try_set_value("America/New_York"));
+        assert!(!tz.try_set_value("Mars/Olympus_Mons"));
+        assert!(!tz.try_set_value(""));
+        assert!(parse(json!({"type": "timezone", "value": "MSK+3"})).is_err());
+        assert!(timezone_names().contains(&"Europe/Moscow"));
+    }
+
+    #[test]
+    fn color() {
+        let mut color = parse(json!({"type": "color", "value": "#FF8800"})).unwrap();
+        assert!(color.try_set_value("#00ff7f"));
+        assert!(color.to_json() == Some(json!("#00ff7f")));
+        assert!(!color.try_set_value("#00ff7f80"));
+        assert!(!color.try_set_value("00ff7f"));
+        assert!(!color.try_set_value("#0f7"));
+        assert!(!color.try_set_value("#00gg7f"));
+        let mut color =
+            parse(json!({"type": "color", "value": "#00000080", "alpha": true})).unwrap();
+        assert!(color.try_set_value("#00ff7f"));
+        assert!(color.try_set_value("#00ff7fcc"));
+        assert!(parse(json!({"type": "color", "value": "#00000080"})).is_err());
+        assert!(parse(json!({"type": "color", "value": "red"})).is_err());
+    }
+
+    #[test]
+    fn default_value() {
+        let item: ConfigItem = serde_json::from_value(json!({
+            "name": "f", "title": "F", "type": "float",
+            "value": 0.5, "min": 0, "max": 2, "default": 1,
+        }))
+        .unwrap();
+        assert!(item.default == Some(json!(1.0)));
+        assert!(item.default_value().unwrap().to_json() == Some(json!(1.0)));
+        assert!(serde_json::to_value(&item).unwrap()["default"] == json!(1.0));
+
+        let item = |default| {
+            serde_json::from_value::<ConfigItem>(json!({
+                "name": "i", "title": "I", "type": "integer",
+                "value": 1, "min": 0, "max": 10, "default": default,
+            }))
+        };
+        assert!(item(json!(10)).is_ok());
+        assert!(item(json!(11)).is_err());
+        assert!(item(json!("1")).is_err());
+
+        let group = json!({
+            "name": "g", "title": "G", "type": "group", "items": [], "default": 1,
+        });
+        assert!(serde_json::from_value::<ConfigItem>(group).is_err());
+        let info = json!({
+            "name": "v", "title": "V", "type": "info", "value": "1.2.3", "default": "0.0.0",
+        });
+        assert!(serde_json::from_value::<ConfigItem>(info).is_err());
+    }
+
+    #[test]
+    fn localized_titles() {
+        let mut item: ConfigItem = serde_json::from_value(json!({
+            "name": "s", "title": {"en-US": "Volume", "ru": "Громкость"},
+            "type": "selection", "value": "a",
+            "options": [{"value": "a", "title": {"en": "Loud", "ru-RU": "Громко"}}],
+        }))
+        .unwrap();
+        let langs = |tags: &[&str]| tags.iter().map(|t| t.parse().unwrap()).collect::<Vec<_>>();
+        assert_eq!(
+            item.title.localize(&langs(&["ru-RU", "en-US"])),
+            "Громкость"
+        );
+        assert_eq!(item.title.localize(&langs(&["de", "en-US"])), "Volume");
+        assert_eq!(item.title.localize(&langs(&["en-GB"])), "Volume");
+        assert_eq!(item.title.localize(&langs(&["de"])), "Volume");
+
+        item.localize(&langs(&["ru"]));
+        assert!(item.title == "Громкость".into());
+        assert!(serde_json::to_value(&item).unwrap()["options"][0]["title"] == json!("Громко"));
+
+        let title = |t| serde_json::from_value::<Title>(t);
+        assert!(title(json!("Volume")).is_ok());
+        assert!(title(json!({})).is_err());
+        assert!(title(json!({"not a tag": "Volume"})).is_err());
+    }
+
+    #[test]
+    fn nested_names() {
+        let mut items: Vec<ConfigItem> = serde_json::from_value(json!([
+            {"name": "a", "title": "A", "type": "bool", "value": false},
+            {"name": "network", "title": "Network", "type": "group", "items": [
+                {"name": "proxy", "title": "Proxy", "type": "group", "items": [
+                    {"name": "host", "title": "Host", "type": "string", "value": "x"},
+                ]},
+            ]},
+        ]))
+        .unwrap();
+
+        let mut names = vec![];
+        visit_settings_mut(&mut items, &mut |name, _| names.push(name.to_owned()));
+        assert_eq!(names, vec!["a", "network.proxy.host"]);
+
+        let bad = parse(json!({"type": "group", "items": [
+            {"name": "a.b", "title": "A", "type": "bool", "value": false},
+        ]}));
+        assert!(bad.is_err());
+        assert!(validate_settings(&items).is_ok());
+
+        let top = |names: &[&str]| {
+            let items: Vec<ConfigItem> = names
+                .iter()
+                .map(|&name| {
+                    ConfigItem::new(name.into(), "A".into(), ConfigValue::Bool(true.into()))
+                })
+                .collect();
+            validate_settings(&items)
+        };
+        assert!(top(&["network.proxy.host"]).is_err());
+        assert!(top(&["a", "a"]).is_err());
+        assert!(top(&[""]).is_err());
+        assert!(top(&["_present"]).is_err());
+        assert!(top(&["a_b"]).is_ok());
+    }
+
+    #[test]
+    fn conditions() {
+        let items: Vec<ConfigItem> = serde_json::from_value(json!([
+            {"name": "use_proxy", "title": "Proxy", "type": "bool", "value": false},
+            {"name": "host", "title": "Host", "type": "string", "value": "",
+                "visible_if": {"name": "use_proxy", "equals": true}},
+            {"name": "mode", "title": "Mode", "type": "selection", "value": "a",
+                "options": [{"value": "a", "title": "A"}, {"value": "b", "title": "B"}]},
+            {"name": "adv", "title": "Advanced", "type": "group",
+                "enabled_if": {"name": "mode", "in": ["b", "c"]},
+                "items": [
+                    {"name": "x", "title": "X", "type": "bool", "value": true},
+                ]},
+        ]))
+        .unwrap();
+
+        let inactive = inactive_settings(&items);
+        assert!(inactive.contains("host"));
+        assert!(inactive.contains("adv.x"));
+        assert!(!inactive.contains("use_proxy"));
+
+        let host = find_setting(&items, "host").unwrap();
+        assert!(host.value == ConfigValue::String("".into()));
+        assert!(find_setting(&items, "adv.y").is_none());
+        assert!(find_setting(&items, "mode.a").is_none());
+        assert!(validate_settings(&items).is_ok());
+
+        let items = |condition| {
+            serde_json::from_value::<Vec<ConfigItem>>(json!([
+                {"name": "f", "title": "F", "type": "float", "min": 0, "max": 2, "value": 1},
+                {"name": "v", "title": "V", "type": "info", "value": "1.0"},
+                {"name": "p", "title": "P", "type": "password", "value": "secret"},
+                {"name": "l", "title": "L", "type": "list", "item_type": "string", "value": []},
+                {"name": "g", "title": "G", "type": "group", "items": [
+                    {"name": "x", "title": "X", "type": "bool", "value": true,
+                        "visible_if": condition},
+                ]},
+            ]))
+            .unwrap()
+        };
+        let holds = |condition| !inactive_settings(&items(condition)).contains("g.x");
+        assert!(holds(json!({"name": "f", "equals": 1})));
+        assert!(holds(json!({"name": "f", "in": [0, 1]})));
+        assert!(!holds(json!({"name": "f", "not_equals": 1.0})));
+        let valid = |condition| validate_settings(&items(condition)).is_ok();
+        assert!(!valid(json!({"name": "use_proxy", "equals": true})));
+        assert!(!valid(json!({"name": "g", "equals": true})));
+        assert!(!valid(json!({"name": "v", "equals": "1.0"})));
+        assert!(!valid(json!({"name": "p", "equals": "secret"})));
+        assert!(!valid(json!({"name": "l", "equals": []})));
+        assert!(valid(json!({"name": "f", "equals": 1})));
+    }
+
+    #[test]
+    fn item_hints() {
+        let v = json!({
+            "name": "delay", "title": "Delay", "type": "integer",
+            "min": 0, "max": 1000, "value": 100,
+            "description": "Audio delay", "placeholder": "0", "unit": "ms",
+            "help_url": "https://example.com/help#delay",
+        });
+        let item: ConfigItem = serde_json::from_value(v.clone()).unwrap();
+        assert_eq!(serde_json::to_value(&item).unwrap(), v);
+
+        let bad = serde_json::from_value::<ConfigItem>(json!({
+            "name": "a", "title": "A", "type": "bool", "value": true,
+            "help_url": "javascript:alert(1)",
+        }));
+        assert!(bad.is_err());
+    }
+
+    #[test]
+    fn text_line_endings() {
+        let mut text = parse(json!({"type": "text", "value": "", "max_length": 6})).unwrap();
+        assert!(text.try_set_value("a\r\nb\rc"));
+        assert!(
+            text == ConfigValue::Text(ConfigText::new("a\nb\nc".into(), None, Some(6)).unwrap())
+        );
+        assert!(!text.try_set_value("a\r\nb\r\nc\r\nd"));
+    }
+
+    #[test]
+    fn password_is_redacted() {
+        let mut item = ConfigItem::new(
+            "p".into(),
+            "Password".into(),
+            ConfigValue::Password("secret".into()),
+        );
+        assert!(item.value.try_set_value(""));
+        assert!(item.value == ConfigValue::Password("secret".into()));
+        assert!(item.redacted().value == ConfigValue::Password("".into()));
+
+        assert!(item.value.try_set_value("changed"));
+        assert!(item.value == ConfigValue::Password("changed".into()));
+    }
+
+    #[test]
+    fn info_is_read_only() {
+        let mut info = parse(json!({"type": "info", "value": "1.2.3"})).unwrap();
+        assert!(info.try_set_value("4.5.6"));
+        assert!(info.to_json() == Some(json!("1.2.3")));
+
+        let banner = json!({"type": "info", "value": "Low disk space", "style": "warning"});
+        assert!(parse(banner).is_ok());
+        let banner = json!({"type": "info", "value": "Low disk space", "style": "blink"});
+        assert!(parse(banner).is_err());
+    }
+
+    #[test]
+    fn action_has_no_value() {
+        let mut action = parse(json!({"type": "action", "confirm": "Reboot now?"})).unwrap();
+        assert!(action.try_set_value("on"));
+        assert!(action.to_json().is_none());
+        assert!(action == ConfigValue::Action(ConfigAction::new(Some("Reboot now?".into()))));
     }
 }
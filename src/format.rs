@@ -0,0 +1,118 @@
+/// Well known formats of string settings
+use serde::{Deserialize, Serialize};
+use std::net::{Ipv4Addr, Ipv6Addr};
+use url::Url;
+
+#[derive(Clone, Copy, PartialEq, Debug, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum StringFormat {
+    Url,
+    Email,
+    Ipv4,
+    Ipv6,
+    Hostname,
+    Mac,
+    #[serde(rename = "host:port")]
+    HostPort,
+}
+
+impl StringFormat {
+    /// returns true when string is written in this format
+    pub fn is_valid(self, v: &str) -> bool {
+        match self {
+            StringFormat::Url => is_url(v),
+            StringFormat::Email => is_email(v),
+            StringFormat::Ipv4 => v.parse::<Ipv4Addr>().is_ok(),
+            StringFormat::Ipv6 => v.parse::<Ipv6Addr>().is_ok(),
+            StringFormat::Hostname => is_hostname(v),
+            StringFormat::Mac => is_mac(v),
+            StringFormat::HostPort => is_host_port(v),
+        }
+    }
+}
+
+fn is_url(v: &str) -> bool {
+    match Url::parse(v) {
+        Ok(url) => url.has_host(),
+        Err(_) => false,
+    }
+}
+
+fn is_email(v: &str) -> bool {
+    match v.rfind('@') {
+        Some(i) => {
+            let (local, domain) = (&v[..i], &v[i + 1..]);
+            !local.is_empty()
+                && !local.chars().any(|c| c.is_whitespace() || c.is_control())
+                && is_hostname(domain)
+        }
+        None => false,
+    }
+}
+
+fn is_hostname(v: &str) -> bool {
+    fn is_label(l: &str) -> bool {
+        !l.is_empty()
+            && l.len() <= 63
+            && !l.starts_with('-')
+            && !l.ends_with('-')
+            && l.chars().all(|c| c.is_ascii_alphanumeric() || c == '-')
+    }
+    v.len() <= 253 && v.split('.').all(is_label)
+}
+
+fn is_mac(v: &str) -> bool {
+    let parts = v.split(&[':', '-'][..]).collect::<Vec<_>>();
+    parts.len() == 6
+        && parts
+            .iter()
+            .all(|p| p.len() == 2 && p.chars().all(|c| c.is_ascii_hexdigit()))
+}
+
+fn is_host_port(v: &str) -> bool {
+    let i = match v.rfind(':') {
+        Some(i) => i,
+        None => return false,
+    };
+    let (host, port) = (&v[..i], &v[i + 1..]);
+    let host_ok = if host.starts_with('[') && host.ends_with(']') {
+        host[1..host.len() - 1].parse::<Ipv6Addr>().is_ok()
+    } else {
+        host.parse::<Ipv4Addr>().is_ok() || is_hostname(host)
+    };
+    host_ok && matches!(port.parse::<u16>(), Ok(p) if p != 0)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::StringFormat::*;
+
+    #[test]
+    fn formats() {
+        assert!(Url.is_valid("http://example.com/list.m3u?a=1"));
+        assert!(!Url.is_valid("example.com"));
+
+        assert!(Email.is_valid("user@example.com"));
+        assert!(!Email.is_valid("user@"));
+        assert!(!Email.is_valid("us er@example.com"));
+
+        assert!(Ipv4.is_valid("192.168.1.1"));
+        assert!(!Ipv4.is_valid("192.168.1"));
+        assert!(Ipv6.is_valid("fe80::1"));
+        assert!(!Ipv6.is_valid("192.168.1.1"));
+
+        assert!(Hostname.is_valid("my-box.local"));
+        assert!(!Hostname.is_valid("-box.local"));
+        assert!(!Hostname.is_valid("box..local"));
+
+        assert!(Mac.is_valid("00:1A:2b:3c:4D:5e"));
+        assert!(Mac.is_valid("00-1a-2b-3c-4d-5e"));
+        assert!(!Mac.is_valid("00:1a:2b:3c:4d"));
+
+        assert!(HostPort.is_valid("example.com:8080"));
+        assert!(HostPort.is_valid("10.0.0.1:80"));
+        assert!(HostPort.is_valid("[::1]:80"));
+        assert!(!HostPort.is_valid("example.com"));
+        assert!(!HostPort.is_valid("example.com:0"));
+    }
+}
@@ -1,3 +1,5 @@
 pub mod config;
+pub mod format;
+pub mod schema;
 /// Common modules for different binaries in the package
 pub mod views;
@@ -11,21 +11,27 @@ use futures::prelude::*;
 use lazy_static::lazy_static;
 use mime;
 use serde::{Deserialize, Serialize};
-use serde_json::json;
+use serde_json::{json, Value};
 use std::collections::HashMap;
 use std::error::Error as StdError;
 use std::sync::Mutex;
+use std::time::Duration;
 
 use fluent_templates::{fs::LanguageIdentifier, static_loader, FluentLoader, Loader};
+use qrcode::QrCode;
 use tera::{Context, Tera};
-use url::form_urlencoded;
+use url::{form_urlencoded, Url};
 
 mod config;
-use crate::config::ConfigItem;
+use crate::config::{validate_settings, ConfigItem};
+
+mod format;
 
 mod model;
 use crate::model::Model;
-use crate::model::Secret;
+use crate::model::{PollError, Reset, Secret, UpdateError};
+
+mod schema;
 
 mod views;
 use crate::views::{IndexPage, Page, PolicyPage, SettingsPage, SubmittedPage, LOCALES, TERA};
@@ -69,10 +75,26 @@ impl FromRequest for Langs {
     }
 }
 
+/// Picks the first of `langs` we have translation for, matching either
+/// the whole language tag or only the language, e.g. `ru` for `ru-RU`
+fn negotiate_language(langs: &[LanguageIdentifier]) -> LanguageIdentifier {
+    let locales: Vec<&LanguageIdentifier> = LOCALES.locales().collect();
+    langs
+        .iter()
+        .find_map(|lang| {
+            locales
+                .iter()
+                .find(|&&l| l == lang)
+                .or_else(|| locales.iter().find(|l| l.language == lang.language))
+        })
+        .map(|&l| l.clone())
+        .unwrap_or_else(|| DEFAULT_LANGUAGE.parse().unwrap())
+}
+
 fn render(
     template_name: &str,
     context: &Context,
-    langs: &[LanguageIdentifier],
+    lang: LanguageIdentifier,
 ) -> tera::Result<String> {
     fn trace_error(e: tera::Error) -> tera::Error {
         if let Some(s) = e.source() {
@@ -89,15 +111,6 @@ fn render(
         t.full_reload().unwrap();
     }
 
-    let default_lang = DEFAULT_LANGUAGE.parse().unwrap();
-    let requested_lang = langs.first().unwrap_or(&default_lang);
-
-    let lang = LOCALES
-        .locales()
-        .find(|&l| l == requested_lang)
-        .map(|l| l.clone())
-        .unwrap_or(default_lang);
-
     t.register_function(
         "fluent",
         FluentLoader::new(LOCALES.deref()).with_default_lang(lang),
@@ -105,12 +118,16 @@ fn render(
     t.render(template_name, context).map_err(trace_error)
 }
 
-fn render_page<T>(data: T, langs: &[LanguageIdentifier]) -> Result<HttpResponse, Error>
+fn render_page<T>(mut data: T, langs: &[LanguageIdentifier]) -> Result<HttpResponse, Error>
 where
     T: Page + Serialize,
 {
+    // Texts of the device are shown in the same language as the page
+    let lang = negotiate_language(langs);
+    data.localize(&[lang.clone(), DEFAULT_LANGUAGE.parse().unwrap()]);
+
     let ctx = Context::from_serialize(data).map_err(error::ErrorInternalServerError)?;
-    render(T::TEMPLATE_NAME, &ctx, langs)
+    render(T::TEMPLATE_NAME, &ctx, lang)
         .map(|b| {
             HttpResponse::Ok()
                 .content_type(mime::TEXT_HTML.as_ref())
@@ -132,6 +149,38 @@ where
         .map_err(error::ErrorInternalServerError)
 }
 
+#[derive(Deserialize, Clone, Copy)]
+#[serde(rename_all = "lowercase")]
+enum ImageFormat {
+    Png,
+    Svg,
+}
+
+fn render_qr(data: &str, format: ImageFormat) -> Result<HttpResponse, Error> {
+    let code = QrCode::new(data).map_err(error::ErrorInternalServerError)?;
+    match format {
+        ImageFormat::Svg => {
+            let image = code
+                .render::<qrcode::render::svg::Color>()
+                .min_dimensions(200, 200)
+                .build();
+            Ok(HttpResponse::Ok()
+                .content_type(mime::IMAGE_SVG.as_ref())
+                .body(image))
+        }
+        ImageFormat::Png => {
+            let image = code.render::<image::Luma<u8>>().build();
+            let mut bytes = Vec::new();
+            image::DynamicImage::ImageLuma8(image)
+                .write_to(&mut bytes, image::ImageOutputFormat::Png)
+                .map_err(error::ErrorInternalServerError)?;
+            Ok(HttpResponse::Ok()
+                .content_type(mime::IMAGE_PNG.as_ref())
+                .body(bytes))
+        }
+    }
+}
+
 fn redirect(location: &str) -> HttpResponse {
     HttpResponse::Found()
         .header(http::header::LOCATION, location)
@@ -202,13 +251,13 @@ async fn get_settings(
     secret_opt
         .as_ref()
         .map(|secret| {
-            let config_opt = {
+            let page_opt = {
                 let mut m = model.inner.lock().unwrap();
-                let s = m.settings(&secret).map(|v| v.clone());
+                let s = m.settings(&secret).map(|v| SettingsPage::new(v));
                 s
             };
-            match config_opt {
-                Ok(config) => render_page(SettingsPage { config }, langs.as_ref()),
+            match page_opt {
+                Ok(page) => render_page(page, langs.as_ref()),
                 // TODO: Flash message
                 Err(_) => Ok(redirect("./")),
             }
@@ -229,31 +278,88 @@ async fn post_settings(
             return Ok(redirect("./"));
         }
     };
-    let form_data = form_urlencoded::parse(&body).into_owned();
-    let values = form_data.collect::<HashMap<String, String>>();
+    // Same key is repeated for settings with multiple values, their order is kept for lists
+    let mut values = HashMap::<String, Vec<String>>::new();
+    for (k, v) in form_urlencoded::parse(&body).into_owned() {
+        values.entry(k).or_default().push(v);
+    }
+    // Unchecked checkboxes are not submitted at all, the marker tells they are empty
+    if let Some(present) = values.remove(PRESENT_FIELD) {
+        for name in present {
+            values.entry(name).or_default();
+        }
+    }
+    // Reset buttons tell which settings get their default values
+    let reset = if values.remove(RESET_ALL_FIELD).is_some() {
+        Reset::All
+    } else {
+        match values.remove(RESET_FIELD) {
+            Some(names) => Reset::Settings(names.into_iter().collect()),
+            None => Reset::Nothing,
+        }
+    };
     let result = {
         let mut m = model.inner.lock().unwrap();
-        m.update_settings(&secret, values)
+        m.update_settings(&secret, &values, &reset)
     };
     match result {
         Ok(_) => render_page(SubmittedPage {}, langs.as_ref()),
-        Err(msg) => Ok(HttpResponse::BadRequest()
-            .content_type("text/html")
-            .body(msg)),
+        Err(UpdateError::InvalidSession) => Ok(redirect("./")),
+        Err(UpdateError::InvalidValues { settings, errors }) => {
+            // Show rejected input to the user, so that it can be corrected
+            let submitted = errors
+                .keys()
+                .filter_map(|name| {
+                    let v = values.get(name)?.first()?;
+                    Some((name.clone(), v.clone()))
+                })
+                .collect();
+            let page = SettingsPage {
+                errors,
+                submitted,
+                ..SettingsPage::new(&settings)
+            };
+            render_page(page, langs.as_ref()).map(|mut response| {
+                *response.status_mut() = http::StatusCode::BAD_REQUEST;
+                response
+            })
+        }
     }
 }
 
 async fn new_session(
     model: web::Data<ModelState>,
-    config: web::Json<Vec<ConfigItem>>,
+    config: web::Json<Value>,
 ) -> Result<HttpResponse, Error> {
-    let (key, secret) = model.inner.lock().unwrap().new_client(config.into_inner());
+    // Settings are described either in our own format or with JSON Schema of an object
+    let config = match config.into_inner() {
+        schema @ Value::Object(_) => schema::import(&schema),
+        items => serde_json::from_value::<Vec<ConfigItem>>(items).map_err(|e| e.to_string()),
+    };
+    let config = config.and_then(|items| {
+        validate_settings(&items).map_err(String::from)?;
+        Ok(items)
+    });
+    let config = match config {
+        Ok(config) => config,
+        Err(msg) => {
+            return Ok(HttpResponse::BadRequest()
+                .content_type("text/plain")
+                .body(msg))
+        }
+    };
+    let (key, secret) = model.inner.lock().unwrap().new_client(config);
     render_json(&json!({
         "key": key,
         "secret": secret.to_string(),
     }))
 }
 
+/// JSON Schema of the settings accepted by `/stb/new-session`
+async fn settings_schema() -> Result<HttpResponse, Error> {
+    render_json(&schema::settings_schema())
+}
+
 #[derive(Deserialize)]
 struct SessionQuery {
     sid: Secret,
@@ -275,6 +381,63 @@ async fn end_session(
     Ok(response.finish())
 }
 
+#[derive(Deserialize)]
+struct QrQuery {
+    sid: Secret,
+    format: Option<ImageFormat>,
+}
+
+/// End point for device to get QR code with the login url
+/// that contains single time access key
+async fn session_qr(
+    req: HttpRequest,
+    model: web::Data<ModelState>,
+    public_url: web::Data<PublicUrl>,
+    query: web::Query<QrQuery>,
+) -> Result<HttpResponse, Error> {
+    let result = {
+        let mut m = model.inner.lock().unwrap();
+        m.access_key(&query.sid)
+    };
+    let key = match result {
+        Ok(key) => key,
+        Err(_) => return Ok(HttpResponse::NotFound().finish()),
+    };
+    let url = public_url.login_url(&req, &key)?;
+    render_qr(url.as_str(), query.format.unwrap_or(ImageFormat::Png))
+}
+
+/// Sends command of the action button to the device
+async fn post_action(
+    model: web::Data<ModelState>,
+    session: Session,
+    body: web::Bytes,
+) -> Result<HttpResponse, Error> {
+    let secret: Secret = match session.get::<Secret>(SESSION_SECRET)? {
+        Some(s) => s.to_owned(),
+        None => {
+            return Ok(redirect("./"));
+        }
+    };
+    let name = form_urlencoded::parse(&body)
+        .into_owned()
+        .find(|(k, _)| k == ACTION_FIELD)
+        .map(|(_, v)| v)
+        .unwrap_or_default();
+    let result = {
+        let mut m = model.inner.lock().unwrap();
+        m.request_action(&secret, &name)
+    };
+    match result {
+        // Unsaved changes are lost without javascript, which sends actions in background
+        Ok(_) => Ok(redirect("./settings")),
+        Err("invalid-session") => Ok(redirect("./")),
+        Err(msg) => Ok(HttpResponse::BadRequest()
+            .content_type("text/html")
+            .body(msg)),
+    }
+}
+
 #[derive(Deserialize)]
 struct PollQuery {
     sid: Secret,
@@ -284,20 +447,43 @@ struct PollQuery {
 /// End point for device to poll changes made by user
 async fn poll_session(
     model: web::Data<ModelState>,
+    timeout: web::Data<PollTimeout>,
     query: web::Query<PollQuery>,
 ) -> Result<HttpResponse, Error> {
     let fut = {
         let mut m = model.inner.lock().unwrap();
         m.values(&query.sid, query.revision)
     };
-    match fut.await {
-        Ok(values) => render_json(&values),
-        Err(_) => Ok(HttpResponse::NotFound().finish()),
+    match actix_rt::time::timeout(timeout.0, fut).await {
+        Ok(Ok(values)) => render_json(&values),
+        Ok(Err(PollError::Expired)) => Ok(HttpResponse::Gone().finish()),
+        Ok(Err(PollError::Invalid)) => Ok(HttpResponse::NotFound().finish()),
+        // Nothing has changed, the device polls again and so keeps the session alive
+        Err(_) => Ok(HttpResponse::NoContent().finish()),
     }
 }
 
 const SESSION_SECRET: &str = "secret";
 
+/// Form field with the names of the settings present in the form.
+/// Names of control fields start with underscore, which is reserved for them
+const PRESENT_FIELD: &str = "_present";
+
+/// Form field with the name of the setting to be reset to its default
+const RESET_FIELD: &str = "_reset";
+
+/// Form field telling that all settings are reset to their defaults
+const RESET_ALL_FIELD: &str = "_reset_all";
+
+/// Form field with the name of the pressed action button
+const ACTION_FIELD: &str = "_action";
+
+/// How often expired sessions are removed
+const CLEANUP_INTERVAL: Duration = Duration::from_secs(60);
+
+/// Longest time a poll request waits for changes
+const POLL_TIMEOUT: Duration = Duration::from_secs(60);
+
 struct ModelState {
     inner: Mutex<Model>,
 }
@@ -310,6 +496,51 @@ impl From<Model> for ModelState {
     }
 }
 
+/// Address of the index page as seen by users.
+/// When not configured it is guessed from the request
+struct PublicUrl(Option<Url>);
+
+impl PublicUrl {
+    fn resolve(&self, req: &HttpRequest) -> Result<Url, Error> {
+        match &self.0 {
+            Some(url) => Ok(url.clone()),
+            None => {
+                let info = req.connection_info();
+                Url::parse(&format!("{}://{}/", info.scheme(), info.host()))
+                    .map_err(error::ErrorInternalServerError)
+            }
+        }
+    }
+
+    /// Index page that logs the user in with the access key
+    fn login_url(&self, req: &HttpRequest, key: &str) -> Result<Url, Error> {
+        let mut url = self.resolve(req)?;
+        url.query_pairs_mut().clear().append_pair("c", key);
+        Ok(url)
+    }
+}
+
+/// Time after which a waiting poll request is answered with no changes.
+/// It is shorter than the session ttl, so that alive devices are never removed
+struct PollTimeout(Duration);
+
+impl PollTimeout {
+    fn new(session_ttl: u32) -> Self {
+        Self(POLL_TIMEOUT.min(Duration::from_secs(session_ttl as u64) / 2))
+    }
+}
+
+/// Periodically removes inactive sessions from the model
+fn spawn_cleanup(state: web::Data<ModelState>, period: Duration) {
+    actix_rt::spawn(async move {
+        let mut interval = actix_rt::time::interval(period);
+        loop {
+            interval.tick().await;
+            state.inner.lock().unwrap().cleanup();
+        }
+    });
+}
+
 /// Configure routes
 fn app_config(cfg: &mut web::ServiceConfig) {
     cfg.service(
@@ -323,9 +554,12 @@ fn app_config(cfg: &mut web::ServiceConfig) {
             .route(web::get().to(get_settings))
             .route(web::post().to(post_settings)),
     )
+    .route("/action", web::post().to(post_action))
     .route("/stb/new-session", web::post().to(new_session))
+    .route("/stb/schema.json", web::get().to(settings_schema))
     .route("/stb/del-session", web::get().to(end_session))
-    .route("/stb/poll", web::get().to(poll_session));
+    .route("/stb/poll", web::get().to(poll_session))
+    .route("/stb/qr", web::get().to(session_qr));
 }
 
 #[actix_rt::main]
@@ -344,6 +578,23 @@ async fn main() -> std::io::Result<()> {
                 .default_value("8000")
                 .help("The port to listen to"),
         )
+        .arg(
+            clap::Arg::with_name("session-ttl")
+                .long("session-ttl")
+                .env("APP_SESSION_TTL")
+                .takes_value(true)
+                .default_value("86400")
+                .help("Seconds of inactivity after which a session is removed"),
+        )
+        .arg(
+            clap::Arg::with_name("public-url")
+                .long("public-url")
+                .env("APP_PUBLIC_URL")
+                .takes_value(true)
+                .help(
+                    "Address of the web interface used in QR codes, e.g. https://example.com/web/",
+                ),
+        )
         .get_matches();
 
     let port = {
@@ -354,17 +605,37 @@ async fn main() -> std::io::Result<()> {
         })
     };
 
+    let session_ttl = {
+        let s = args.value_of("session-ttl").unwrap();
+        s.parse::<u32>().unwrap_or_else(|e| {
+            eprintln!("Bad session-ttl argument '{}', {}.", s, e);
+            std::process::exit(1);
+        })
+    };
+
+    let public_url = args.value_of("public-url").map(|s| {
+        Url::parse(s).unwrap_or_else(|e| {
+            eprintln!("Bad public-url argument '{}', {}.", s, e);
+            std::process::exit(1);
+        })
+    });
+
     env_logger::init();
     let addr = format!("127.0.0.1:{}", port);
     println!("Starting web server at {}", addr);
 
     // Global shared state variable
-    let state = web::Data::new(ModelState::from(Model::new()));
+    let state = web::Data::new(ModelState::from(Model::new(session_ttl)));
+    spawn_cleanup(state.clone(), CLEANUP_INTERVAL);
+    let public_url = web::Data::new(PublicUrl(public_url));
+    let poll_timeout = web::Data::new(PollTimeout::new(session_ttl));
 
     HttpServer::new(move || {
         // Remember to update middleware configuration in tests
         App::new()
             .app_data(state.clone())
+            .app_data(public_url.clone())
+            .app_data(poll_timeout.clone())
             .wrap(middleware::Logger::default())
             // FIXME: Generate random key
             .wrap(CookieSession::signed(&[0; 32]).secure(false))
@@ -389,11 +660,15 @@ mod tests {
     fn build_test_server() -> TestServer {
         env_logger::init();
 
-        let state = web::Data::new(ModelState::from(Model::new()));
+        let state = web::Data::new(ModelState::from(Model::new(60)));
+        let public_url = web::Data::new(PublicUrl(None));
+        let poll_timeout = web::Data::new(PollTimeout::new(60));
 
         test::start(move || {
             App::new()
                 .app_data(state.clone())
+                .app_data(public_url.clone())
+                .app_data(poll_timeout.clone())
                 .wrap(middleware::Logger::default())
                 .wrap(CookieSession::signed(&[0; 32]).secure(false))
                 .configure(app_config)
@@ -471,12 +746,7 @@ mod tests {
             eprintln!("Got revision r{}", result.revision);
             assert_eq!(result.revision, 0);
             let a = result.values.iter().find(|v| v.name == "a").unwrap();
-            assert!(
-                a.value
-                    == ConfigValue::String(ConfigString {
-                        value: "qwerty".to_owned()
-                    })
-            );
+            assert!(a.value == ConfigValue::String(ConfigString::from("qwerty")));
 
             // Future replies increment revision and give new values
             eprintln!("Poll...");
@@ -489,12 +759,7 @@ mod tests {
             eprintln!("Got revision r{}", result.revision);
             assert_eq!(result.revision, 1);
             let a = result.values.iter().find(|v| v.name == "a").unwrap();
-            assert!(
-                a.value
-                    == ConfigValue::String(ConfigString {
-                        value: "sometext".to_owned()
-                    })
-            );
+            assert!(a.value == ConfigValue::String(ConfigString::from("sometext")));
 
             // After Stb got updated values it usually deletes session
             eprintln!("End session");
@@ -554,4 +819,96 @@ mod tests {
         // Wait for Stb to poll all changes
         rx.await.unwrap();
     }
+
+    #[test]
+    fn language_negotiation() {
+        let negotiate = |tags: &[&str]| {
+            let langs: Vec<_> = tags.iter().map(|t| t.parse().unwrap()).collect();
+            negotiate_language(&langs).to_string()
+        };
+        assert_eq!(negotiate(&["ru-RU", "ru"]), "ru");
+        assert_eq!(negotiate(&["de", "en-GB", "ru"]), "en-US");
+        assert_eq!(negotiate(&["de"]), DEFAULT_LANGUAGE);
+        assert_eq!(negotiate(&[]), DEFAULT_LANGUAGE);
+    }
+
+    #[actix_rt::test]
+    async fn qr_code() {
+        let state = web::Data::new(ModelState::from(Model::new(60)));
+        let (key, secret) = state.inner.lock().unwrap().new_client(vec![]);
+        let mut app = test::init_service(
+            App::new()
+                .app_data(state.clone())
+                .app_data(web::Data::new(PublicUrl(None)))
+                .configure(app_config),
+        )
+        .await;
+
+        let uri = format!("/stb/qr?sid={}&format=svg", secret.to_string());
+        let res =
+            test::call_service(&mut app, test::TestRequest::get().uri(&uri).to_request()).await;
+        assert_eq!(res.status(), StatusCode::OK);
+        assert_eq!(
+            res.headers().get(header::CONTENT_TYPE).unwrap(),
+            "image/svg+xml"
+        );
+
+        let uri = format!("/stb/qr?sid={}", secret.to_string());
+        let res =
+            test::call_service(&mut app, test::TestRequest::get().uri(&uri).to_request()).await;
+        assert_eq!(res.status(), StatusCode::OK);
+        assert_eq!(
+            res.headers().get(header::CONTENT_TYPE).unwrap(),
+            "image/png"
+        );
+
+        // Encoded url logs in with the key shown to the device
+        let shown = state.inner.lock().unwrap().access_key(&secret).unwrap();
+        assert_eq!(shown, key);
+        let req = test::TestRequest::default().to_http_request();
+        let public_url = PublicUrl(Some(Url::parse("https://example.com/web/?x=1").unwrap()));
+        assert_eq!(
+            public_url.login_url(&req, &shown).unwrap().as_str(),
+            format!("https://example.com/web/?c={}", key)
+        );
+        let url = PublicUrl(None).login_url(&req, &shown).unwrap();
+        assert_eq!(url.as_str(), format!("http://localhost:8080/?c={}", key));
+
+        let uri = "/stb/qr?sid=unknown";
+        let res =
+            test::call_service(&mut app, test::TestRequest::get().uri(uri).to_request()).await;
+        assert_eq!(res.status(), StatusCode::NOT_FOUND);
+    }
+
+    #[actix_rt::test]
+    async fn poll_timeout_and_expiry() {
+        let state = web::Data::new(ModelState::from(Model::new(60)));
+        let (_, secret) = state.inner.lock().unwrap().new_client(vec![]);
+        let mut app = test::init_service(
+            App::new()
+                .app_data(state.clone())
+                .app_data(web::Data::new(PollTimeout(Duration::from_millis(10))))
+                .configure(app_config),
+        )
+        .await;
+        let uri = format!("/stb/poll?sid={}&revision=0", secret.to_string());
+        let poll = || test::TestRequest::get().uri(&uri).to_request();
+
+        // Waiting device is told to poll again
+        let res = test::call_service(&mut app, poll()).await;
+        assert_eq!(res.status(), StatusCode::NO_CONTENT);
+        assert!(state.inner.lock().unwrap().settings(&secret).is_ok());
+
+        // Session is removed while the device is waiting
+        *state.inner.lock().unwrap() = Model::new(0);
+        let (_, secret) = state.inner.lock().unwrap().new_client(vec![]);
+        let uri = format!("/stb/poll?sid={}&revision=0", secret.to_string());
+        let poll = test::call_service(&mut app, test::TestRequest::get().uri(&uri).to_request());
+        let cleanup = async {
+            actix_rt::time::delay_for(Duration::from_millis(1)).await;
+            state.inner.lock().unwrap().cleanup();
+        };
+        let (res, _) = future::join(poll, cleanup).await;
+        assert_eq!(res.status(), StatusCode::GONE);
+    }
 }
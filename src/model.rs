@@ -1,5 +1,5 @@
 /// This module describes the main logic of web-settings service
-use super::config::ConfigItem;
+use super::config::{find_setting, inactive_settings, visit_settings_mut, ConfigItem, ConfigValue};
 use futures::future;
 use futures::future::BoxFuture;
 use futures_util::future::FutureExt;
@@ -21,6 +21,9 @@ impl From<Secret> for String {
     }
 }
 
+/// Revision of values the device is expected to have.
+/// Pressed actions are queued separately and delivered only when the device
+/// polls with the current revision, so they never replace new values.
 enum ClientSt {
     Created,
     Submitted(u32),
@@ -32,7 +35,57 @@ pub struct Values {
     pub values: Vec<ConfigItem>,
 }
 
-type Message = Result<Values, ()>;
+/// Reasons for a poll request to fail
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum PollError {
+    /// Session does not exist, or the poll request was superseded by a newer one
+    Invalid,
+    /// Session was removed after being inactive for too long
+    Expired,
+}
+
+/// Message delivered to the device by a poll request
+#[derive(Clone, Serialize, Deserialize)]
+#[serde(untagged)]
+pub enum Event {
+    /// User has logged in or submitted new values
+    Values(Values),
+    /// User has pressed action buttons, values of the revision are not changed
+    Actions { revision: u32, actions: Vec<String> },
+}
+
+type Message = Result<Event, PollError>;
+
+/// Error message ids by full setting names
+pub type Errors = HashMap<String, &'static str>;
+
+/// Settings which are reset to their default values instead of the submitted ones
+pub enum Reset {
+    Nothing,
+    All,
+    Settings(HashSet<String>),
+}
+
+impl Reset {
+    fn contains(&self, name: &str) -> bool {
+        match self {
+            Reset::Nothing => false,
+            Reset::All => true,
+            Reset::Settings(names) => names.contains(name),
+        }
+    }
+}
+
+/// Reasons for submitted settings to be rejected
+pub enum UpdateError {
+    InvalidSession,
+    /// Some values are not valid, nothing is changed in this case
+    InvalidValues {
+        /// Settings with all valid values applied
+        settings: Vec<ConfigItem>,
+        errors: Errors,
+    },
+}
 
 use futures::channel::oneshot;
 use futures::channel::oneshot::{Receiver, Sender};
@@ -41,6 +94,10 @@ struct Client {
     settings: Vec<ConfigItem>,
     st: ClientSt,
     sender: Option<Sender<Message>>,
+    /// Full names of pressed actions not delivered to the device yet
+    actions: Vec<String>,
+    /// Timestamp of the last request made by the device or by the user
+    last_active: u64,
 }
 
 impl Client {
@@ -49,12 +106,50 @@ impl Client {
             settings,
             st: ClientSt::Created,
             sender: None,
+            actions: Vec::new(),
+            last_active: timestamp(),
+        }
+    }
+
+    fn touch(&mut self) {
+        self.last_active = timestamp();
+    }
+
+    /// Alive device polls again before the ttl as its poll requests time out
+    fn is_expired(&self, now: u64, ttl: u64) -> bool {
+        now.saturating_sub(self.last_active) >= ttl
+    }
+
+    /// Resolve pending poll request (if any) telling that the session is gone
+    fn expire(&mut self) {
+        if self.sender.is_some() {
+            self.send_message(Err(PollError::Expired));
         }
     }
 
     /// Notify receiver about changed settings
     fn send(&mut self) {
-        self.send_message(Ok(self.current_values()));
+        self.send_message(Ok(Event::Values(self.current_values())));
+    }
+
+    /// Queues the action and delivers the queue if the device is waiting
+    fn push_action(&mut self, name: String) {
+        if !self.actions.contains(&name) {
+            self.actions.push(name);
+        }
+        if let Some(sender) = self.sender.take() {
+            // Actions are kept until the device gets them, e.g. after its poll request is dropped
+            if let Err(Ok(Event::Actions { actions, .. })) = sender.send(Ok(self.take_actions())) {
+                self.actions = actions;
+            }
+        }
+    }
+
+    fn take_actions(&mut self) -> Event {
+        Event::Actions {
+            revision: self.revision(),
+            actions: std::mem::take(&mut self.actions),
+        }
     }
 
     fn get_receiver(&mut self) -> Receiver<Message> {
@@ -65,7 +160,7 @@ impl Client {
     }
 
     fn send_err(&mut self) {
-        self.send_message(Err(()));
+        self.send_message(Err(PollError::Invalid));
     }
 
     fn send_message(&mut self, message: Message) {
@@ -79,6 +174,13 @@ impl Client {
         }
     }
 
+    /// Replaces settings with the new validated ones and notifies the receiver
+    fn commit(&mut self, settings: Vec<ConfigItem>) {
+        self.settings = settings;
+        self.update_rev();
+        self.send();
+    }
+
     fn update_rev(&mut self) {
         self.st = match self.st {
             ClientSt::Created => ClientSt::Submitted(1),
@@ -86,18 +188,80 @@ impl Client {
         };
     }
 
+    fn revision(&self) -> u32 {
+        match self.st {
+            ClientSt::Created => 0,
+            ClientSt::Submitted(r) => r,
+        }
+    }
+
     fn current_values(&self) -> Values {
         Values {
-            revision: match self.st {
-                ClientSt::Created => 0,
-                ClientSt::Submitted(r) => r,
-            },
+            revision: self.revision(),
             values: self.settings.clone(),
         }
     }
 }
 
-use std::collections::HashMap;
+use std::collections::{HashMap, HashSet};
+
+/// Validates submitted values on a scratch copy of the current settings,
+/// an empty list of values clears the setting, e.g. an unchecked checkbox.
+/// The current settings are never modified, on failure the copy is returned
+/// together with the errors, so that the user can correct them.
+fn apply_values(
+    current: &[ConfigItem],
+    values: &HashMap<String, Vec<String>>,
+    reset: &Reset,
+) -> Result<Vec<ConfigItem>, (Vec<ConfigItem>, Errors)> {
+    let mut settings = current.to_vec();
+    let mut errors = HashMap::new();
+    visit_settings_mut(&mut settings, &mut |name, item| {
+        // Read-only items are never changed by the user
+        if reset.contains(name) && item.value.is_editable() {
+            if let Some(value) = item.default_value() {
+                item.value = value;
+                return;
+            }
+        }
+        // Settings that are not submitted are left unchanged
+        let ok = match values.get(name) {
+            Some(v) => item.value.try_set_values(v),
+            None => return,
+        };
+        if !ok {
+            errors.insert(name.to_owned(), "invalid-value");
+        } else if item.required && item.value.is_empty() {
+            errors.insert(name.to_owned(), "value-required");
+        }
+    });
+
+    // Hidden and disabled settings keep their previous values
+    let inactive = inactive_settings(&settings);
+    visit_settings_mut(&mut settings, &mut |name, item| {
+        if inactive.contains(name) {
+            if let Some(old) = find_setting(current, name) {
+                item.value = old.value.clone();
+            }
+        }
+    });
+    errors.retain(|name, _| !inactive.contains(name));
+    if errors.is_empty() {
+        Ok(settings)
+    } else {
+        Err((settings, errors))
+    }
+}
+
+/// Current unix time in seconds
+fn timestamp() -> u64 {
+    use std::time::UNIX_EPOCH;
+    // Must not panic because now is later than epoch
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap()
+        .as_secs()
+}
 
 #[derive(Debug)]
 struct Payload<T> {
@@ -120,15 +284,6 @@ impl<T> KeyStorage<T> {
         }
     }
 
-    fn timestamp() -> u64 {
-        use std::time::UNIX_EPOCH;
-        // Must not panic because now is later than epoch
-        SystemTime::now()
-            .duration_since(UNIX_EPOCH)
-            .unwrap()
-            .as_secs()
-    }
-
     pub fn new_key(&mut self, data: T) -> String {
         use std::collections::hash_map::Entry;
         for _ in 0..2 {
@@ -137,7 +292,7 @@ impl<T> KeyStorage<T> {
                 Entry::Vacant(v) => {
                     v.insert(Payload {
                         data: data,
-                        timestamp: Self::timestamp(),
+                        timestamp: timestamp(),
                     });
                     return key;
                 }
@@ -153,8 +308,7 @@ impl<T> KeyStorage<T> {
     pub fn take_data(&mut self, key: &str) -> Result<T, &'static str> {
         match self.keys.remove(key) {
             Some(v) => {
-                let t = Self::timestamp();
-                if t - v.timestamp < self.expiration as u64 {
+                if timestamp().saturating_sub(v.timestamp) < self.expiration as u64 {
                     Ok(v.data)
                 } else {
                     Err("key-expired")
@@ -164,8 +318,26 @@ impl<T> KeyStorage<T> {
         }
     }
 
+    /// Finds a valid key that holds the given data
+    pub fn find_key(&self, data: &T) -> Option<&str>
+    where
+        T: PartialEq,
+    {
+        let now = timestamp();
+        self.keys
+            .iter()
+            .find(|(_, v)| {
+                v.data == *data && now.saturating_sub(v.timestamp) < self.expiration as u64
+            })
+            .map(|(k, _)| k.as_str())
+    }
+
+    /// Removes all expired keys
     fn cleanup(&mut self) {
-        // TODO: remove all expired keys
+        let now = timestamp();
+        let expiration = self.expiration as u64;
+        self.keys
+            .retain(|_, v| now.saturating_sub(v.timestamp) < expiration);
     }
 
     fn random_key(&mut self) -> String {
@@ -180,14 +352,17 @@ pub struct Model {
     clients: HashMap<Secret, Client>,
     keys: KeyStorage<Secret>,
     rng: SecretRng,
+    /// Seconds of inactivity after which client is removed
+    session_ttl: u32,
 }
 
 impl Model {
-    pub fn new() -> Self {
+    pub fn new(session_ttl: u32) -> Self {
         Self {
             clients: HashMap::new(),
             keys: KeyStorage::new(10 * 60),
             rng: make_rng(),
+            session_ttl,
         }
     }
 
@@ -223,72 +398,99 @@ impl Model {
     /// Previous sender (if any) will be drop,
     /// so previous futures returned from this method are going to resolve with error
     pub fn values(&mut self, sid: &Secret, revision: u32) -> BoxFuture<'static, Message> {
-        let client = self.clients.get_mut(sid).ok_or(());
-        let client = match client {
-            Ok(c) => c,
-            Err(_) => return future::err(()).boxed(),
+        let client = match self.clients.get_mut(sid) {
+            Some(c) => c,
+            None => return future::err(PollError::Invalid).boxed(),
         };
+        client.touch();
         match client.st {
             ClientSt::Created => {
                 if revision != 0 {
                     // must never happen
-                    return future::err(()).boxed();
+                    return future::err(PollError::Invalid).boxed();
                 }
-                // recreate communication channel and wait for login
-                let f = client.get_receiver().map(|res| res.unwrap_or(Err(())));
-                return Box::pin(f);
             }
             ClientSt::Submitted(current_rev) => {
                 if revision < current_rev {
                     // we have newer revision immediately
-                    return future::ok(client.current_values()).boxed();
-                } else if revision == current_rev {
-                    // recreate communication channel and wait for new values
-                    let f = client.get_receiver().map(|res| res.unwrap_or(Err(())));
-                    return Box::pin(f);
-                } else {
+                    return future::ok(Event::Values(client.current_values())).boxed();
+                } else if revision > current_rev {
                     // must never happen
-                    return future::err(()).boxed();
+                    return future::err(PollError::Invalid).boxed();
                 }
             }
         }
+        // actions are delivered only when the device has the current values
+        if !client.actions.is_empty() {
+            return future::ok(client.take_actions()).boxed();
+        }
+        // recreate communication channel and wait for login, new values or actions
+        let f = client
+            .get_receiver()
+            .map(|res| res.unwrap_or(Err(PollError::Invalid)));
+        Box::pin(f)
+    }
+
+    /// Returns single time access key of the client that has not logged in yet
+    pub fn access_key(&mut self, sid: &Secret) -> Result<String, &'static str> {
+        let client = self.clients.get_mut(sid).ok_or("invalid-session")?;
+        client.touch();
+        self.keys
+            .find_key(sid)
+            .map(|k| k.to_owned())
+            .ok_or("key-expired")
     }
 
     pub fn auth(&mut self, key: &str) -> Result<Secret, &'static str> {
         let secret = self.keys.take_data(key)?;
         let client = self.clients.get_mut(&secret).ok_or("session-expired")?;
+        client.touch();
         client.send();
         Ok(secret)
     }
 
     pub fn settings(&mut self, s: &Secret) -> Result<&Vec<ConfigItem>, &'static str> {
         self.clients
-            .get(s)
-            .map(|c| &c.settings)
+            .get_mut(s)
+            .map(|c| {
+                c.touch();
+                &c.settings
+            })
             .ok_or("invalid-session")
     }
 
+    /// Applies submitted values, either all of them or nothing.
+    /// Reset is processed as a normal update with a new revision
     pub fn update_settings(
         &mut self,
         s: &Secret,
-        values: HashMap<String, String>,
-    ) -> Result<(), &'static str> {
-        let client = self.clients.get_mut(s).ok_or("invalid-session")?;
+        values: &HashMap<String, Vec<String>>,
+        reset: &Reset,
+    ) -> Result<(), UpdateError> {
+        let client = self.clients.get_mut(s).ok_or(UpdateError::InvalidSession)?;
+        client.touch();
+        let settings = apply_values(&client.settings, values, reset)
+            .map_err(|(settings, errors)| UpdateError::InvalidValues { settings, errors })?;
+        client.commit(settings);
+        Ok(())
+    }
 
-        for s in client.settings.iter_mut() {
-            match values.get(&s.name) {
-                Some(v) => {
-                    if !s.value.try_set_value(v) {
-                        return Err("bad value");
-                    }
-                }
-                None => {
-                    s.value.try_set_value("");
-                }
-            }
+    /// Sends the command of the action button to the device,
+    /// settings are not changed
+    pub fn request_action(&mut self, s: &Secret, name: &str) -> Result<(), &'static str> {
+        let client = self.clients.get_mut(s).ok_or("invalid-session")?;
+        client.touch();
+        match find_setting(&client.settings, name) {
+            Some(ConfigItem {
+                value: ConfigValue::Action(_),
+                ..
+            }) => {}
+            _ => return Err("invalid-action"),
         }
-        client.update_rev();
-        client.send();
+        if inactive_settings(&client.settings).contains(name) {
+            return Err("invalid-action");
+        }
+        client.push_action(name.to_owned());
         Ok(())
     }
 
@@ -298,8 +500,21 @@ impl Model {
         Secret(base64::encode_config(&bytes[..], base64::URL_SAFE_NO_PAD))
     }
 
-    fn cleanup(&mut self) {
-        unimplemented!()
+    /// Removes clients that were inactive longer than the session ttl
+    /// together with expired access keys.
+    /// Pending poll requests of removed clients are resolved with `PollError::Expired`
+    pub fn cleanup(&mut self) {
+        let now = timestamp();
+        let ttl = self.session_ttl as u64;
+        self.clients.retain(|_, c| {
+            if c.is_expired(now, ttl) {
+                c.expire();
+                false
+            } else {
+                true
+            }
+        });
+        self.keys.cleanup();
     }
 }
 
@@ -318,3 +533,291 @@ fn make_rng() -> SecretRng {
     // Reseed every 32KiB.
     ReseedingRng::new(rng, 32_768, OsRng)
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::ConfigInfo;
+    use futures::executor::block_on;
+
+    #[test]
+    fn cleanup_expires_sessions() {
+        let mut m = Model::new(0);
+        let (key, secret) = m.new_client(vec![]);
+        let poll = m.values(&secret, 0);
+
+        m.cleanup();
+
+        assert_eq!(block_on(poll).err(), Some(PollError::Expired));
+        assert!(m.settings(&secret).is_err());
+        assert_eq!(m.auth(&key), Err("session-expired"));
+    }
+
+    #[test]
+    fn access_key_is_reused() {
+        let mut m = Model::new(60);
+        let (key, secret) = m.new_client(vec![]);
+        assert_eq!(m.access_key(&secret), Ok(key.clone()));
+        assert_eq!(m.access_key(&secret), Ok(key.clone()));
+
+        // Key is single time, it is not shown after the user has logged in
+        m.auth(&key).unwrap();
+        assert_eq!(m.access_key(&secret), Err("key-expired"));
+        m.remove_client(&secret).unwrap();
+        assert_eq!(m.access_key(&secret), Err("invalid-session"));
+        let unknown = Secret("unknown".into());
+        assert_eq!(m.access_key(&unknown), Err("invalid-session"));
+
+        // Session outlives its access key
+        m.keys = KeyStorage::new(0);
+        let (key, secret) = m.new_client(vec![]);
+        assert_eq!(m.access_key(&secret), Err("key-expired"));
+        assert_eq!(m.auth(&key), Err("key-expired"));
+    }
+
+    #[test]
+    fn hidden_settings_are_kept() {
+        let mut m = Model::new(60);
+        let settings = serde_json::from_value(serde_json::json!([
+            {"name": "use_proxy", "title": "Proxy", "type": "bool", "value": true},
+            {"name": "host", "title": "Host", "type": "string", "value": "proxy.lan",
+                "visible_if": {"name": "use_proxy", "equals": true}},
+        ]))
+        .unwrap();
+        let (_, secret) = m.new_client(settings);
+
+        // Unchecked checkbox hides the host field, which is then submitted empty
+        let mut values = HashMap::new();
+        values.insert("use_proxy".to_owned(), vec![]);
+        values.insert("host".to_owned(), vec!["".to_owned()]);
+        assert!(m.update_settings(&secret, &values, &Reset::Nothing).is_ok());
+
+        let settings = m.settings(&secret).unwrap();
+        let host = find_setting(settings, "host").unwrap();
+        assert!(host.value == ConfigValue::String("proxy.lan".into()));
+        let proxy = find_setting(settings, "use_proxy").unwrap();
+        assert!(proxy.value.to_json() == Some(serde_json::Value::Bool(false)));
+    }
+
+    #[test]
+    fn all_errors_are_reported() {
+        let mut m = Model::new(60);
+        let settings = serde_json::from_value(serde_json::json!([
+            {"name": "a", "title": "A", "type": "string", "value": "x", "required": true},
+            {"name": "b", "title": "B", "type": "integer", "value": 1, "min": 0, "max": 10},
+            {"name": "c", "title": "C", "type": "string", "value": "y"},
+        ]))
+        .unwrap();
+        let (_, secret) = m.new_client(settings);
+
+        let mut values = HashMap::new();
+        values.insert("a".to_owned(), vec!["".to_owned()]);
+        values.insert("b".to_owned(), vec!["11".to_owned()]);
+        values.insert("c".to_owned(), vec!["z".to_owned()]);
+        match m.update_settings(&secret, &values, &Reset::Nothing) {
+            Err(UpdateError::InvalidValues { settings, errors }) => {
+                assert_eq!(errors.len(), 2);
+                assert_eq!(errors["a"], "value-required");
+                assert_eq!(errors["b"], "invalid-value");
+                let c = find_setting(&settings, "c").unwrap();
+                assert!(c.value == ConfigValue::String("z".into()));
+            }
+            _ => panic!("values must be rejected"),
+        }
+    }
+
+    #[test]
+    fn missing_settings_are_kept() {
+        let mut m = Model::new(60);
+        let settings = serde_json::from_value(serde_json::json!([
+            {"name": "a", "title": "A", "type": "string", "value": "x", "required": true},
+            {"name": "b", "title": "B", "type": "bool", "value": true},
+            {"name": "c", "title": "C", "type": "bool", "value": true},
+        ]))
+        .unwrap();
+        let (_, secret) = m.new_client(settings);
+
+        let mut values = form(&[]);
+        values.insert("c".to_owned(), vec![]);
+        assert!(m.update_settings(&secret, &values, &Reset::Nothing).is_ok());
+
+        let settings = m.settings(&secret).unwrap();
+        let a = find_setting(settings, "a").unwrap();
+        assert!(a.value == ConfigValue::String("x".into()));
+        let b = find_setting(settings, "b").unwrap();
+        assert!(b.value.to_json() == Some(serde_json::Value::Bool(true)));
+        let c = find_setting(settings, "c").unwrap();
+        assert!(c.value.to_json() == Some(serde_json::Value::Bool(false)));
+    }
+
+    fn revision(message: Message) -> u32 {
+        match message {
+            Ok(Event::Values(v)) => v.revision,
+            Ok(Event::Actions { revision, .. }) => revision,
+            Err(_) => panic!("poll request failed"),
+        }
+    }
+
+    fn current_values(message: Message) -> Values {
+        match message {
+            Ok(Event::Values(v)) => v,
+            _ => panic!("values are expected"),
+        }
+    }
+
+    #[test]
+    fn actions_are_delivered() {
+        let mut m = Model::new(60);
+        let settings = serde_json::from_value(serde_json::json!([
+            {"name": "a", "title": "A", "type": "string", "value": "x"},
+            {"name": "reboot", "title": "Reboot", "type": "action"},
+            {"name": "scan", "title": "Scan", "type": "action"},
+        ]))
+        .unwrap();
+        let (key, secret) = m.new_client(settings);
+        m.auth(&key).unwrap();
+        assert_eq!(m.request_action(&secret, "a"), Err("invalid-action"));
+        assert_eq!(m.request_action(&secret, "missing"), Err("invalid-action"));
+
+        // Waiting poll request is resolved at once
+        let poll = m.values(&secret, 0);
+        m.request_action(&secret, "reboot").unwrap();
+        match block_on(poll) {
+            Ok(Event::Actions { revision, actions }) => {
+                assert_eq!(revision, 0);
+                assert_eq!(actions, vec!["reboot"]);
+            }
+            _ => panic!("actions are expected"),
+        }
+
+        // Actions pressed while the device is busy are queued
+        m.request_action(&secret, "scan").unwrap();
+        m.request_action(&secret, "reboot").unwrap();
+        assert!(m
+            .update_settings(&secret, &form(&[("a", "y")]), &Reset::Nothing)
+            .is_ok());
+        assert_eq!(revision(block_on(m.values(&secret, 0))), 1);
+        match block_on(m.values(&secret, 1)) {
+            Ok(Event::Actions { revision, actions }) => {
+                assert_eq!(revision, 1);
+                assert_eq!(actions, vec!["scan", "reboot"]);
+            }
+            _ => panic!("actions are expected"),
+        }
+        assert!(m.values(&secret, 1).now_or_never().is_none());
+    }
+
+    #[test]
+    fn actions_survive_dropped_poll() {
+        let mut m = Model::new(60);
+        let settings = serde_json::from_value(serde_json::json!([
+            {"name": "reboot", "title": "Reboot", "type": "action"},
+        ]))
+        .unwrap();
+        let (key, secret) = m.new_client(settings);
+        m.auth(&key).unwrap();
+
+        // Connection of the poll request is lost before the button is pressed
+        drop(m.values(&secret, 0));
+        m.request_action(&secret, "reboot").unwrap();
+        match m.values(&secret, 0).now_or_never() {
+            Some(Ok(Event::Actions { actions, .. })) => assert_eq!(actions, vec!["reboot"]),
+            _ => panic!("actions are expected"),
+        }
+    }
+
+    #[test]
+    fn reset_to_defaults() {
+        let mut m = Model::new(60);
+        let settings = serde_json::from_value(serde_json::json!([
+            {"name": "a", "title": "A", "type": "string", "value": "x", "default": "a"},
+            {"name": "b", "title": "B", "type": "integer", "value": 5, "min": 0, "max": 10,
+                "default": 1},
+            {"name": "c", "title": "C", "type": "string", "value": "y"},
+        ]))
+        .unwrap();
+        let (_, secret) = m.new_client(settings);
+
+        // Reset setting is not validated
+        let values = form(&[("a", "z"), ("b", "11"), ("c", "z")]);
+        let reset = Reset::Settings(vec!["b".to_owned()].into_iter().collect());
+        assert!(m.update_settings(&secret, &values, &reset).is_ok());
+        let settings = m.settings(&secret).unwrap();
+        let a = find_setting(settings, "a").unwrap();
+        assert!(a.value == ConfigValue::String("z".into()));
+        let b = find_setting(settings, "b").unwrap();
+        assert!(b.value.to_json() == Some(serde_json::json!(1)));
+
+        assert!(m.update_settings(&secret, &form(&[]), &Reset::All).is_ok());
+        let values = current_values(block_on(m.values(&secret, 1)));
+        assert_eq!(values.revision, 2);
+        let a = find_setting(&values.values, "a").unwrap();
+        assert!(a.value == ConfigValue::String("a".into()));
+        let c = find_setting(&values.values, "c").unwrap();
+        assert!(c.value == ConfigValue::String("z".into()));
+    }
+
+    #[test]
+    fn reset_keeps_read_only_items() {
+        let mut m = Model::new(60);
+        let mut version = ConfigItem::new(
+            "version".into(),
+            "Version".into(),
+            ConfigValue::Info(ConfigInfo::new("1.2.3".into(), None)),
+        );
+        version.default = Some(serde_json::json!("0.0.0"));
+        let (_, secret) = m.new_client(vec![version]);
+
+        assert!(m.update_settings(&secret, &form(&[]), &Reset::All).is_ok());
+        let settings = m.settings(&secret).unwrap();
+        let version = find_setting(settings, "version").unwrap();
+        assert!(version.value.to_json() == Some(serde_json::json!("1.2.3")));
+    }
+
+    fn form(values: &[(&str, &str)]) -> HashMap<String, Vec<String>> {
+        values
+            .iter()
+            .map(|(k, v)| (k.to_string(), vec![v.to_string()]))
+            .collect()
+    }
+
+    #[test]
+    fn rejected_update_changes_nothing() {
+        let mut m = Model::new(60);
+        let settings: Vec<ConfigItem> = serde_json::from_value(serde_json::json!([
+            {"name": "a", "title": "A", "type": "string", "value": "x"},
+            {"name": "b", "title": "B", "type": "integer", "value": 1, "min": 0, "max": 10},
+        ]))
+        .unwrap();
+        let (key, secret) = m.new_client(settings.clone());
+        let login = m.values(&secret, 0);
+        m.auth(&key).unwrap();
+        assert_eq!(revision(block_on(login)), 0);
+
+        // The first value is valid, but must not be applied because of the second one
+        let poll = m.values(&secret, 0);
+        assert!(m
+            .update_settings(&secret, &form(&[("a", "y"), ("b", "11")]), &Reset::Nothing)
+            .is_err());
+        assert!(poll.now_or_never().is_none());
+        assert!(m.settings(&secret).unwrap() == &settings);
+
+        assert!(m
+            .update_settings(&secret, &form(&[("a", "y"), ("b", "2")]), &Reset::Nothing)
+            .is_ok());
+        let values = current_values(block_on(m.values(&secret, 0)));
+        assert_eq!(values.revision, 1);
+        let a = find_setting(&values.values, "a").unwrap();
+        assert!(a.value == ConfigValue::String("y".into()));
+
+        // Failure after a successful update keeps the last revision
+        assert!(m
+            .update_settings(&secret, &form(&[("a", "z"), ("b", "")]), &Reset::Nothing)
+            .is_err());
+        let values = current_values(block_on(m.values(&secret, 0)));
+        assert_eq!(values.revision, 1);
+        let a = find_setting(&values.values, "a").unwrap();
+        assert!(a.value == ConfigValue::String("y".into()));
+        assert!(m.values(&secret, 1).now_or_never().is_none());
+    }
+}
@@ -0,0 +1,539 @@
+/// JSON Schema of the settings sent by devices,
+/// and import of settings described with standard JSON Schema
+use crate::config::ConfigItem;
+use serde_json::{json, Map, Value};
+
+/// Schema of the list of settings accepted by `/stb/new-session`
+pub fn settings_schema() -> Value {
+    json!({
+        "$schema": "http://json-schema.org/draft-07/schema#",
+        "title": "Web settings",
+        "type": "array",
+        "items": {"$ref": "#/definitions/item"},
+        "definitions": {
+            "item": item_schema(),
+            "title": {
+                "oneOf": [
+                    {"type": "string"},
+                    {
+                        "description": "Translations by language tags",
+                        "type": "object",
+                        "minProperties": 1,
+                        "additionalProperties": {"type": "string"},
+                    },
+                ],
+            },
+            "condition": {
+                "type": "object",
+                "required": ["name"],
+                "properties": {
+                    "name": {"type": "string"},
+                    "equals": {},
+                    "not_equals": {},
+                    "in": {"type": "array"},
+                },
+                "oneOf": [
+                    {"required": ["equals"]},
+                    {"required": ["not_equals"]},
+                    {"required": ["in"]},
+                ],
+            },
+            "choice": {
+                "type": "object",
+                "required": ["value", "title"],
+                "properties": {
+                    "value": {"type": "string"},
+                    "title": {"$ref": "#/definitions/title"},
+                },
+            },
+        },
+    })
+}
+
+/// Properties common for all settings and constraints of each type
+fn item_schema() -> Value {
+    json!({
+        "type": "object",
+        "required": ["name", "title", "type"],
+        "properties": {
+            "name": {"type": "string", "pattern": "^[^._][^.]*$"},
+            "title": {"$ref": "#/definitions/title"},
+            "type": {"enum": types().iter().map(|(name, _)| name).collect::<Vec<_>>()},
+            "visible_if": {"$ref": "#/definitions/condition"},
+            "enabled_if": {"$ref": "#/definitions/condition"},
+            "description": {"type": "string"},
+            "placeholder": {"type": "string"},
+            "unit": {"type": "string"},
+            "help_url": {"type": "string", "pattern": "^https?://"},
+            "required": {"type": "boolean"},
+            "default": {"description": "Valid value of the setting"},
+        },
+        "oneOf": types()
+            .into_iter()
+            .map(|(name, mut schema)| {
+                schema["properties"]["type"] = json!({"const": name});
+                schema
+            })
+            .collect::<Vec<_>>(),
+    })
+}
+
+fn typed(properties: Value, required: &[&str]) -> Value {
+    json!({"properties": properties, "required": required})
+}
+
+/// Names of setting types with schemas of their own properties
+fn types() -> Vec<(&'static str, Value)> {
+    let length = json!({"type": "integer", "minimum": 0});
+    let string = json!({"type": "string"});
+    let widget = json!({"enum": ["input", "slider"]});
+    let value = |schema: &Value| typed(json!({"value": schema}), &["value"]);
+    vec![
+        (
+            "string",
+            typed(
+                json!({
+                    "value": string,
+                    "min_length": length,
+                    "max_length": length,
+                    "pattern": string,
+                    "format": {
+                        "enum": ["url", "email", "ipv4", "ipv6", "hostname", "mac", "host:port"],
+                    },
+                }),
+                &["value"],
+            ),
+        ),
+        (
+            "text",
+            typed(
+                json!({"value": string, "rows": length, "max_length": length}),
+                &["value"],
+            ),
+        ),
+        (
+            "integer",
+            typed(
+                json!({
+                    "value": {"type": "integer"},
+                    "min": {"type": "integer"},
+                    "max": {"type": "integer"},
+                    "step": {"type": "integer", "exclusiveMinimum": 0},
+                    "widget": widget,
+                }),
+                &["value", "min", "max"],
+            ),
+        ),
+        (
+            "float",
+            typed(
+                json!({
+                    "value": {"type": "number"},
+                    "min": {"type": "number"},
+                    "max": {"type": "number"},
+                    "step": {"type": "number", "exclusiveMinimum": 0},
+                    "precision": {"type": "integer", "minimum": 0, "maximum": 255},
+                    "widget": widget,
+                }),
+                &["value", "min", "max"],
+            ),
+        ),
+        (
+            "selection",
+            typed(
+                json!({
+                    "value": string,
+                    "options": {"type": "array", "items": {"$ref": "#/definitions/choice"}},
+                }),
+                &["value", "options"],
+            ),
+        ),
+        (
+            "multiselection",
+            typed(
+                json!({
+                    "value": {"type": "array", "items": string, "uniqueItems": true},
+                    "options": {"type": "array", "items": {"$ref": "#/definitions/choice"}},
+                    "min": length,
+                    "max": length,
+                }),
+                &["value", "options"],
+            ),
+        ),
+        ("bool", value(&json!({"type": "boolean"}))),
+        ("password", value(&string)),
+        (
+            "group",
+            typed(
+                json!({"items": {"type": "array", "items": {"$ref": "#/definitions/item"}}}),
+                &["items"],
+            ),
+        ),
+        (
+            "info",
+            typed(
+                json!({"value": string, "style": {"enum": ["info", "warning", "danger"]}}),
+                &["value"],
+            ),
+        ),
+        ("action", typed(json!({"confirm": string}), &[])),
+        (
+            "list",
+            typed(
+                json!({
+                    "value": {"type": "array", "items": {"type": ["string", "integer"]}},
+                    "item_type": {"enum": ["string", "integer"]},
+                    "min_length": length,
+                    "max_length": length,
+                    "reorderable": {"type": "boolean"},
+                }),
+                &["value", "item_type"],
+            ),
+        ),
+        (
+            "map",
+            typed(
+                json!({
+                    "value": {"type": "object", "additionalProperties": string},
+                    "key_pattern": string,
+                }),
+                &["value"],
+            ),
+        ),
+        (
+            "date",
+            value(&json!({"type": "string", "pattern": "^(\\d{4}-\\d{2}-\\d{2})?$"})),
+        ),
+        (
+            "time",
+            value(&json!({"type": "string", "pattern": "^(\\d{2}:\\d{2}(:\\d{2})?)?$"})),
+        ),
+        (
+            "datetime",
+            value(&json!({
+                "type": "string",
+                "pattern": "^(\\d{4}-\\d{2}-\\d{2}T\\d{2}:\\d{2}(:\\d{2})?)?$",
+            })),
+        ),
+        ("timezone", value(&string)),
+        (
+            "color",
+            typed(
+                json!({
+                    "value": {"type": "string", "pattern": "^#[0-9a-fA-F]{6}([0-9a-fA-F]{2})?$"},
+                    "alpha": {"type": "boolean"},
+                }),
+                &["value"],
+            ),
+        ),
+    ]
+}
+
+/// Converts JSON Schema of an object into settings, each property becomes a setting.
+/// Only a subset of JSON Schema is supported:
+/// `boolean`, `integer` and `number` with `minimum`, `maximum` and `multipleOf`
+/// (for integers only together with `minimum`),
+/// `string` with `enum`, `format`, `minLength`, `maxLength` and `pattern`,
+/// `array` of `enum` strings (multiple choice) or of strings or integers (list),
+/// `object` with `properties` (group) or with string `additionalProperties` (map).
+/// `title`, `description`, `default`, `readOnly`, `writeOnly` and `required` are used as well.
+pub fn import(schema: &Value) -> Result<Vec<ConfigItem>, String> {
+    let items = object_items(schema)?;
+    serde_json::from_value(Value::Array(items)).map_err(|e| e.to_string())
+}
+
+fn object_items(schema: &Value) -> Result<Vec<Value>, String> {
+    let properties = schema
+        .get("properties")
+        .and_then(Value::as_object)
+        .ok_or("object schema with properties is expected")?;
+    let required = schema
+        .get("required")
+        .and_then(Value::as_array)
+        .cloned()
+        .unwrap_or_default();
+    properties
+        .iter()
+        .map(|(name, property)| {
+            let mut item = property_item(property).map_err(|e| format!("{}: {}", name, e))?;
+            item.insert("name".into(), name.as_str().into());
+            let title = property
+                .get("title")
+                .cloned()
+                .unwrap_or_else(|| name.as_str().into());
+            item.insert("title".into(), title);
+            if let Some(description) = property.get("description") {
+                item.insert("description".into(), description.clone());
+            }
+            if required.contains(&Value::from(name.as_str())) && item["type"] != "group" {
+                item.insert("required".into(), true.into());
+            }
+            Ok(Value::Object(item))
+        })
+        .collect()
+}
+
+/// Setting without name and title
+fn property_item(property: &Value) -> Result<Map<String, Value>, String> {
+    let get = |key: &str| property.get(key).cloned();
+    let default = get("default");
+    let mut item = Map::new();
+    let type_name = match property.get("type").and_then(Value::as_str) {
+        Some("boolean") => {
+            put(
+                &mut item,
+                "value",
+                Some(default.clone().unwrap_or_else(|| false.into())),
+            );
+            "bool"
+        }
+        Some("integer") => {
+            let min = get("minimum").unwrap_or_else(|| i64::MIN.into());
+            let max = get("maximum").unwrap_or_else(|| i64::MAX.into());
+            let value = default.clone().unwrap_or_else(|| clamp_zero(&min, &max));
+            // Steps start at the minimum, not at zero as `multipleOf` does
+            if property.get("minimum").is_some() {
+                put(&mut item, "step", get("multipleOf"));
+            }
+            put(&mut item, "min", Some(min));
+            put(&mut item, "max", Some(max));
+            put(&mut item, "value", Some(value));
+            "integer"
+        }
+        Some("number") => {
+            let min = get("minimum").unwrap_or_else(|| f64::MIN.into());
+            let max = get("maximum").unwrap_or_else(|| f64::MAX.into());
+            let value = default.clone().unwrap_or_else(|| clamp_zero(&min, &max));
+            if property.get("minimum").is_some() {
+                put(&mut item, "step", get("multipleOf"));
+            }
+            put(&mut item, "min", Some(min));
+            put(&mut item, "max", Some(max));
+            put(&mut item, "value", Some(value));
+            "float"
+        }
+        Some("string") => match choices(property) {
+            Some(options) => {
+                let first = options.first().map(|c| c["value"].clone());
+                put(&mut item, "value", default.clone().or(first));
+                put(&mut item, "options", Some(Value::Array(options)));
+                "selection"
+            }
+            None if property.get("readOnly") == Some(&Value::Bool(true)) => {
+                put(&mut item, "value", default.clone().or_else(|| get("const")));
+                return Ok(with_type(item, "info"));
+            }
+            None => {
+                let format = property.get("format").and_then(Value::as_str);
+                put(
+                    &mut item,
+                    "value",
+                    Some(default.clone().unwrap_or_else(|| "".into())),
+                );
+                match format {
+                    Some("date") => "date",
+                    Some("time") => "time",
+                    Some("date-time") => "datetime",
+                    Some("password") => "password",
+                    _ if property.get("writeOnly") == Some(&Value::Bool(true)) => "password",
+                    _ => {
+                        let format = match format {
+                            Some("uri") => Some("url"),
+                            Some(f @ "email") | Some(f @ "ipv4") | Some(f @ "ipv6") => Some(f),
+                            Some(f @ "hostname") => Some(f),
+                            _ => None,
+                        };
+                        put(&mut item, "format", format.map(Value::from));
+                        put(&mut item, "min_length", get("minLength"));
+                        put(&mut item, "max_length", get("maxLength"));
+                        put(&mut item, "pattern", get("pattern"));
+                        "string"
+                    }
+                }
+            }
+        },
+        Some("array") => {
+            let items = property
+                .get("items")
+                .ok_or("array items are not described")?;
+            put(
+                &mut item,
+                "value",
+                Some(default.clone().unwrap_or_else(|| json!([]))),
+            );
+            match choices(items) {
+                Some(options) => {
+                    put(&mut item, "options", Some(Value::Array(options)));
+                    put(&mut item, "min", get("minItems"));
+                    put(&mut item, "max", get("maxItems"));
+                    "multiselection"
+                }
+                None => {
+                    let item_type = match items.get("type").and_then(Value::as_str) {
+                        Some(t @ "string") | Some(t @ "integer") => t,
+                        _ => return Err("only arrays of strings or integers are supported".into()),
+                    };
+                    put(&mut item, "item_type", Some(item_type.into()));
+                    put(&mut item, "min_length", get("minItems"));
+                    put(&mut item, "max_length", get("maxItems"));
+                    "list"
+                }
+            }
+        }
+        Some("object") => {
+            if property.get("properties").is_some() {
+                put(
+                    &mut item,
+                    "items",
+                    Some(Value::Array(object_items(property)?)),
+                );
+                return Ok(with_type(item, "group"));
+            }
+            match property.get("additionalProperties") {
+                Some(values) if values.get("type") == Some(&Value::from("string")) => {
+                    let pattern = property.get("propertyNames").and_then(|p| p.get("pattern"));
+                    put(&mut item, "key_pattern", pattern.cloned());
+                    put(
+                        &mut item,
+                        "value",
+                        Some(default.clone().unwrap_or_else(|| json!({}))),
+                    );
+                    "map"
+                }
+                _ => {
+                    return Err(
+                        "only objects with properties or string values are supported".into(),
+                    )
+                }
+            }
+        }
+        _ => return Err("unsupported type".into()),
+    };
+    put(&mut item, "default", default);
+    Ok(with_type(item, type_name))
+}
+
+fn put(item: &mut Map<String, Value>, key: &str, value: Option<Value>) {
+    if let Some(value) = value {
+        item.insert(key.into(), value);
+    }
+}
+
+fn with_type(mut item: Map<String, Value>, type_name: &str) -> Map<String, Value> {
+    item.insert("type".into(), type_name.into());
+    item
+}
+
+/// Options of `enum`, or of `oneOf` with `const` values and titles
+fn choices(schema: &Value) -> Option<Vec<Value>> {
+    if let Some(values) = schema.get("enum").and_then(Value::as_array) {
+        return Some(
+            values
+                .iter()
+                .map(|v| json!({"value": v, "title": v}))
+                .collect(),
+        );
+    }
+    let variants = schema.get("oneOf").and_then(Value::as_array)?;
+    variants
+        .iter()
+        .map(|v| {
+            let value = v.get("const")?;
+            let title = v.get("title").unwrap_or(value);
+            Some(json!({"value": value, "title": title}))
+        })
+        .collect()
+}
+
+/// Zero if it is in range, otherwise the closest bound
+fn clamp_zero(min: &Value, max: &Value) -> Value {
+    if min.as_f64().filter(|&min| min > 0.0).is_some() {
+        min.clone()
+    } else if max.as_f64().filter(|&max| max < 0.0).is_some() {
+        max.clone()
+    } else {
+        0.into()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::{find_setting, validate_settings, ConfigValue};
+
+    #[test]
+    fn schema_describes_all_types() {
+        let schema = settings_schema();
+        let names = &schema["definitions"]["item"]["properties"]["type"]["enum"];
+        for (name, _) in types() {
+            let item = json!({"name": "a", "title": "A", "type": name});
+            // Every type is known to the deserializer, it fails on missing fields only
+            let err = serde_json::from_value::<ConfigItem>(item).err();
+            assert!(err
+                .filter(|e| e.to_string().contains("unknown variant"))
+                .is_none());
+            assert!(names.as_array().unwrap().contains(&name.into()));
+        }
+
+        // Names are checked the same way as by the server
+        let pattern = schema["definitions"]["item"]["properties"]["name"]["pattern"]
+            .as_str()
+            .unwrap();
+        let pattern = regex::Regex::new(pattern).unwrap();
+        for name in &["a", "a_b", "", "a.b", "_present"] {
+            let item =
+                ConfigItem::new(name.to_string(), "A".into(), ConfigValue::Bool(true.into()));
+            let valid = validate_settings(&[item]).is_ok();
+            assert_eq!(pattern.is_match(name), valid, "{}", name);
+        }
+    }
+
+    #[test]
+    fn import_subset() {
+        let items = import(&json!({
+            "type": "object",
+            "required": ["host"],
+            "properties": {
+                "host": {"type": "string", "title": "Host", "format": "hostname"},
+                "port": {"type": "integer", "minimum": 1, "maximum": 65535, "default": 80},
+                "volume": {"type": "integer", "minimum": 0, "maximum": 100, "multipleOf": 5},
+                "mode": {"type": "string", "oneOf": [
+                    {"const": "a", "title": "Auto"},
+                    {"const": "m", "title": "Manual"},
+                ]},
+                "proxy": {"type": "object", "properties": {
+                    "enabled": {"type": "boolean"},
+                    "headers": {"type": "object", "additionalProperties": {"type": "string"}},
+                }},
+                "groups": {"type": "array", "items": {"type": "string"}, "maxItems": 10},
+                "days": {"type": "array", "items": {"enum": ["mon", "tue"]}},
+                "version": {"type": "string", "readOnly": true, "default": "1.2.3"},
+            },
+        }))
+        .unwrap();
+        let names: Vec<_> = items.iter().map(|i| i.name.as_str()).collect();
+        assert_eq!(
+            names,
+            ["host", "port", "volume", "mode", "proxy", "groups", "days", "version"]
+        );
+
+        let setting = |name| find_setting(&items, name).map(|i| serde_json::to_value(i).unwrap());
+        let host = setting("host").unwrap();
+        assert_eq!(host["format"], "hostname");
+        assert_eq!(host["required"], true);
+        let port = setting("port").unwrap();
+        assert_eq!((&port["value"], &port["default"]), (&json!(80), &json!(80)));
+        assert_eq!(setting("volume").unwrap()["step"], 5);
+        assert_eq!(setting("mode").unwrap()["options"][1]["title"], "Manual");
+        assert_eq!(setting("proxy.enabled").unwrap()["type"], "bool");
+        assert_eq!(setting("proxy.headers").unwrap()["type"], "map");
+        assert_eq!(setting("groups").unwrap()["type"], "list");
+        assert_eq!(setting("days").unwrap()["type"], "multiselection");
+        assert_eq!(setting("version").unwrap()["value"], "1.2.3");
+
+        assert!(import(&json!({"type": "object", "properties": {"a": {"type": "null"}}})).is_err());
+        let invalid_default =
+            json!({"properties": {"a": {"type": "integer", "maximum": 1, "default": 2}}});
+        assert!(import(&invalid_default).is_err());
+    }
+}
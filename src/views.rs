@@ -1,7 +1,14 @@
-use crate::config::{Choice, ConfigBool, ConfigInteger, ConfigItem, ConfigSelection, ConfigValue};
-use fluent_templates::{static_loader, FluentLoader};
+use crate::config::{
+    any_setting, timezone_names, Choice, ConfigAction, ConfigBool, ConfigColor, ConfigDate,
+    ConfigDateTime, ConfigFloat, ConfigGroup, ConfigInfo, ConfigInteger, ConfigItem, ConfigList,
+    ConfigMap, ConfigMultiSelection, ConfigSelection, ConfigText, ConfigTime, ConfigTimezone,
+    ConfigValue, InfoStyle, ListItemType, Title, Widget,
+};
+use core::convert::TryInto;
+use fluent_templates::{static_loader, FluentLoader, LanguageIdentifier};
 use lazy_static::lazy_static;
 use serde::Serialize;
+use std::collections::{BTreeMap, HashMap};
 use std::ops::Deref;
 use std::sync::Mutex;
 use tera::Tera;
@@ -9,6 +16,8 @@ use tera::Tera;
 pub trait Page {
     const TEMPLATE_NAME: &'static str;
     fn mock() -> Self;
+    /// Translates texts provided by the device to the first of `langs` available
+    fn localize(&mut self, _langs: &[LanguageIdentifier]) {}
 }
 
 // Tera templates
@@ -39,8 +48,9 @@ static_loader! {
 // Testing
 
 #[cfg(test)]
-fn mock_render<T: Serialize + Page>(data: T) {
+fn mock_render<T: Serialize + Page>(mut data: T) {
     use tera::Context;
+    data.localize(&["ru".parse().unwrap()]);
     let ctx = Context::from_serialize(data).unwrap();
     let mut t = TERA.lock().unwrap();
     t.register_function(
@@ -82,26 +92,106 @@ test_page!(IndexPage);
 #[derive(Serialize)]
 pub struct SettingsPage {
     pub config: Vec<ConfigItem>,
+    /// Error message ids by full setting names
+    pub errors: HashMap<String, &'static str>,
+    /// Rejected user input by full setting names
+    pub submitted: HashMap<String, String>,
+    /// Names of time zones, only if there are time zone settings
+    pub timezones: Vec<&'static str>,
+    /// Some settings can be reset to their defaults
+    pub has_defaults: bool,
+}
+impl SettingsPage {
+    /// Settings page with secret values removed
+    pub fn new(config: &[ConfigItem]) -> Self {
+        Self {
+            config: config.iter().map(ConfigItem::redacted).collect(),
+            errors: HashMap::new(),
+            submitted: HashMap::new(),
+            has_defaults: any_setting(config, &|item| item.default.is_some()),
+            timezones: if any_setting(config, &|item| {
+                matches!(item.value, ConfigValue::Timezone(_))
+            }) {
+                timezone_names()
+            } else {
+                Vec::new()
+            },
+        }
+    }
 }
 impl Page for SettingsPage {
     const TEMPLATE_NAME: &'static str = "pages/settings.html";
+    fn localize(&mut self, langs: &[LanguageIdentifier]) {
+        for item in self.config.iter_mut() {
+            item.localize(langs);
+        }
+    }
     fn mock() -> Self {
+        let errors = vec![("b", "invalid-value"), ("g.h", "value-required")];
+        let submitted = vec![("b", "1000")];
         Self {
+            timezones: timezone_names(),
+            has_defaults: true,
+            errors: errors.into_iter().map(|(k, v)| (k.into(), v)).collect(),
+            submitted: submitted
+                .into_iter()
+                .map(|(k, v)| (k.into(), v.into()))
+                .collect(),
             config: vec![
+                ConfigItem::new(
+                    "w".into(),
+                    "Test W".into(),
+                    ConfigValue::Info(ConfigInfo::new(
+                        "Warning banner".into(),
+                        Some(InfoStyle::Warning),
+                    )),
+                ),
+                ConfigItem::new(
+                    "v".into(),
+                    "Test V".into(),
+                    ConfigValue::Info(ConfigInfo::new("1.2.3".into(), None)),
+                ),
                 ConfigItem {
-                    name: "a".into(),
-                    title: "Test A".into(),
-                    value: ConfigValue::String("qwerty".into()),
+                    default: Some("asdf".into()),
+                    ..ConfigItem::new(
+                        "a".into(),
+                        "Test A".into(),
+                        ConfigValue::String("qwerty".into()),
+                    )
                 },
+                ConfigItem::new(
+                    "t".into(),
+                    "Test T".into(),
+                    ConfigValue::Text(
+                        ConfigText::new("first line\nsecond line".into(), Some(3), Some(1000))
+                            .unwrap(),
+                    ),
+                ),
                 ConfigItem {
-                    name: "b".into(),
-                    title: "Test B".into(),
-                    value: ConfigValue::Integer(ConfigInteger::new(0, 100, 33).unwrap()),
+                    unit: Some("%".into()),
+                    ..ConfigItem::new(
+                        "b".into(),
+                        "Test B".into(),
+                        ConfigValue::Integer(
+                            ConfigInteger::new(0, 100, Some(5), 35)
+                                .unwrap()
+                                .with_widget(Widget::Slider),
+                        ),
+                    )
                 },
-                ConfigItem {
-                    name: "c".into(),
-                    title: "Test C".into(),
-                    value: ConfigValue::Selection(
+                ConfigItem::new(
+                    "f".into(),
+                    "Test F".into(),
+                    ConfigValue::Float(
+                        ConfigFloat::new(0.5, 2.5, Some(0.05), Some(2), 1.25)
+                            .unwrap()
+                            .with_widget(Widget::Slider),
+                    ),
+                ),
+                ConfigItem::new(
+                    "c".into(),
+                    "Test C".into(),
+                    ConfigValue::Selection(
                         ConfigSelection::new(
                             "foo".into(),
                             vec![
@@ -111,11 +201,121 @@ impl Page for SettingsPage {
                         )
                         .unwrap(),
                     ),
-                },
+                ),
+                ConfigItem::new(
+                    "m".into(),
+                    "Test M".into(),
+                    ConfigValue::MultiSelection(
+                        ConfigMultiSelection::new(
+                            vec!["foo".into(), "baz".into()],
+                            vec![
+                                Choice::new("foo".into(), "Use Foo".into()),
+                                Choice::new("bar".into(), "Use Bar".into()),
+                                Choice::new(
+                                    "baz".into(),
+                                    Title::Localized(
+                                        vec![
+                                            ("en".to_owned(), "Use Baz".to_owned()),
+                                            ("ru".to_owned(), "Использовать Baz".to_owned()),
+                                        ]
+                                        .into_iter()
+                                        .collect::<BTreeMap<_, _>>()
+                                        .try_into()
+                                        .unwrap(),
+                                    ),
+                                ),
+                            ],
+                            Some(1),
+                            None,
+                        )
+                        .unwrap(),
+                    ),
+                ),
+                ConfigItem::new(
+                    "d".into(),
+                    "Test D".into(),
+                    ConfigValue::Bool(ConfigBool::new(true)),
+                ),
+                ConfigItem::new(
+                    "p".into(),
+                    "Test P".into(),
+                    ConfigValue::Password("".into()),
+                ),
+                ConfigItem::new(
+                    "l".into(),
+                    "Test L".into(),
+                    ConfigValue::List(
+                        ConfigList::new(
+                            vec!["news".into(), "sport".into()],
+                            ListItemType::String,
+                            true,
+                        )
+                        .unwrap(),
+                    ),
+                ),
+                ConfigItem::new(
+                    "e".into(),
+                    "Test E".into(),
+                    ConfigValue::Map(
+                        ConfigMap::new(
+                            vec![("User-Agent".into(), "stb".into())]
+                                .into_iter()
+                                .collect(),
+                            Some("[A-Za-z-]+".into()),
+                        )
+                        .unwrap(),
+                    ),
+                ),
+                ConfigItem::new(
+                    "da".into(),
+                    "Test Date".into(),
+                    ConfigValue::Date(ConfigDate::new("2020-12-31".into()).unwrap()),
+                ),
+                ConfigItem::new(
+                    "ti".into(),
+                    "Test Time".into(),
+                    ConfigValue::Time(ConfigTime::new("23:30".into()).unwrap()),
+                ),
+                ConfigItem::new(
+                    "dt".into(),
+                    "Test DateTime".into(),
+                    ConfigValue::DateTime(ConfigDateTime::new("".into()).unwrap()),
+                ),
+                ConfigItem::new(
+                    "tz".into(),
+                    "Test Timezone".into(),
+                    ConfigValue::Timezone(ConfigTimezone::new("Europe/Moscow".into()).unwrap()),
+                ),
+                ConfigItem::new(
+                    "co".into(),
+                    "Test Color".into(),
+                    ConfigValue::Color(ConfigColor::new("#336699cc".into(), true).unwrap()),
+                ),
+                ConfigItem::new(
+                    "r".into(),
+                    "Test R".into(),
+                    ConfigValue::Action(ConfigAction::new(Some("Are you sure?".into()))),
+                ),
                 ConfigItem {
-                    name: "d".into(),
-                    title: "Test D".into(),
-                    value: ConfigValue::Bool(ConfigBool::new(true)),
+                    description: Some("Nested settings".into()),
+                    ..ConfigItem::new(
+                        "g".into(),
+                        "Test Group".into(),
+                        ConfigValue::Group(
+                            ConfigGroup::new(vec![ConfigItem {
+                                unit: Some("ms".into()),
+                                placeholder: Some("0".into()),
+                                ..ConfigItem::new(
+                                    "h".into(),
+                                    "Test H".into(),
+                                    ConfigValue::Integer(
+                                        ConfigInteger::new(0, 100, None, 33).unwrap(),
+                                    ),
+                                )
+                            }])
+                            .unwrap(),
+                        ),
+                    )
                 },
             ],
         }